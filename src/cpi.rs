@@ -0,0 +1,127 @@
+//! Helpers for programs that check membership in a tree through CPI.
+
+use borsh::{BorshDeserialize, BorshSerialize};
+use solana_program::{
+    account_info::AccountInfo,
+    entrypoint::ProgramResult,
+    hash::Hash,
+    instruction::Instruction,
+    program::{get_return_data, invoke, set_return_data},
+    program_error::ProgramError,
+};
+
+pub use crate::instruction::{verify_proof, verify_proof_for_hash};
+
+/// Outcome of a `VerifyProof` instruction, returned via return data.
+#[derive(BorshSerialize, BorshDeserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum VerificationResult {
+    /// The proof leads from the leaf to the tree's current root.
+    Valid,
+
+    /// The proof is well formed but does not lead to any of the tree's roots.
+    InvalidProof,
+
+    /// The leaf index, the proof length or the leaf encoding does not fit the tree.
+    MalformedInput,
+
+    /// The proof leads from the leaf to the root the tree had `root_age`
+    /// changes ago, which is still in its root history. Appended last so the
+    /// other outcomes keep their original encoding.
+    ValidHistoricRoot { root_age: u8 },
+}
+
+impl VerificationResult {
+    /// Outcome of a proof leading to the root from `root_age` changes ago.
+    pub fn valid(root_age: u8) -> Self {
+        match root_age {
+            0 => VerificationResult::Valid,
+            root_age => VerificationResult::ValidHistoricRoot { root_age },
+        }
+    }
+
+    /// Whether the proof leads to the current root or a historic one.
+    pub fn is_valid(&self) -> bool {
+        matches!(
+            self,
+            VerificationResult::Valid | VerificationResult::ValidHistoricRoot { .. }
+        )
+    }
+}
+
+/// Sets `result` as the return data of a verify instruction, so CPI callers
+/// can branch on it.
+pub(crate) fn set_verification_result(result: &VerificationResult) -> ProgramResult {
+    set_return_data(&borsh::to_vec(result).map_err(|e| ProgramError::BorshIoError(e.to_string()))?);
+    Ok(())
+}
+
+/// Invokes `VerifyProof` on `tree` and returns its outcome.
+///
+/// An `Err` means the verification could not run at all (e.g. `tree` is not a
+/// tree account of `merkle_program`); otherwise the caller branches on the
+/// returned `VerificationResult`.
+pub fn invoke_verify_proof<'a>(
+    merkle_program: &AccountInfo<'a>,
+    tree: &AccountInfo<'a>,
+    leaf_index: u32,
+    leaf_data: &[u8],
+    proof: &[Hash],
+) -> Result<VerificationResult, ProgramError> {
+    let instruction = verify_proof(merkle_program.key, tree.key, leaf_index, leaf_data, proof)?;
+    invoke_verification(merkle_program, tree, &instruction)
+}
+
+/// Invokes `VerifyProofForHash` on `tree`, a tree of pre-hashed leaves, and
+/// returns its outcome like `invoke_verify_proof`.
+pub fn invoke_verify_proof_for_hash<'a>(
+    merkle_program: &AccountInfo<'a>,
+    tree: &AccountInfo<'a>,
+    leaf_index: u32,
+    leaf: Hash,
+    proof: &[Hash],
+) -> Result<VerificationResult, ProgramError> {
+    let instruction = verify_proof_for_hash(merkle_program.key, tree.key, leaf_index, leaf, proof)?;
+    invoke_verification(merkle_program, tree, &instruction)
+}
+
+/// Invokes a verify `instruction` on `tree` and decodes its return data.
+fn invoke_verification<'a>(
+    merkle_program: &AccountInfo<'a>,
+    tree: &AccountInfo<'a>,
+    instruction: &Instruction,
+) -> Result<VerificationResult, ProgramError> {
+    invoke(instruction, &[tree.clone(), merkle_program.clone()])?;
+
+    let (program_id, data) = get_return_data().ok_or(ProgramError::InvalidAccountData)?;
+    if program_id != *merkle_program.key {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+    VerificationResult::try_from_slice(&data).map_err(|e| ProgramError::BorshIoError(e.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn current_root_outcomes_keep_their_original_encoding() {
+        // The return data written by the original `VerifyProof`.
+        for (data, result) in [
+            ([0], VerificationResult::Valid),
+            ([1], VerificationResult::InvalidProof),
+            ([2], VerificationResult::MalformedInput),
+        ] {
+            assert_eq!(VerificationResult::try_from_slice(&data).unwrap(), result);
+            assert_eq!(borsh::to_vec(&result).unwrap(), data);
+        }
+
+        let historic = VerificationResult::ValidHistoricRoot { root_age: 5 };
+        assert_eq!(borsh::to_vec(&historic).unwrap(), [3, 5]);
+        assert_eq!(
+            VerificationResult::try_from_slice(&[3, 5]).unwrap(),
+            historic
+        );
+        assert_eq!(VerificationResult::valid(0), VerificationResult::Valid);
+        assert_eq!(VerificationResult::valid(5), historic);
+    }
+}
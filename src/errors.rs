@@ -2,7 +2,7 @@ use solana_program::program_error::ProgramError;
 use thiserror::Error;
 
 /// Custom errors for the Merkle tree program
-#[derive(Error, Debug, Copy, Clone)]
+#[derive(Error, Debug, Copy, Clone, PartialEq, Eq)]
 pub enum MerkleTreeError {
     /// Account is already initialized
     #[error("The account is already initialized")]
@@ -23,6 +23,38 @@ pub enum MerkleTreeError {
     /// Invalid proof
     #[error("Invalid proof provided")]
     InvalidProof,
+
+    /// Account data was written with an unknown layout version
+    #[error("Unsupported tree account version")]
+    UnsupportedAccountVersion,
+
+    /// The leaves do not fit into the tree account
+    #[error("The tree account is too small for the number of leaves")]
+    TreeCapacityExceeded,
+
+    /// The stored leaf differs from the one the caller expected
+    #[error("The stored leaf does not match the expected leaf")]
+    LeafMismatch,
+
+    /// The tree authority did not sign the instruction
+    #[error("The tree authority must sign this instruction")]
+    Unauthorized,
+
+    /// Raw leaf data was sent for a tree of pre-hashed leaves, or vice versa
+    #[error("The leaf encoding does not match the tree")]
+    LeafEncodingMismatch,
+
+    /// The instruction needs the leaves, but the tree only stores its root
+    #[error("The tree does not store its leaves")]
+    LeavesNotStored,
+
+    /// The account holds a different kind of tree than the instruction expects
+    #[error("The account holds a different kind of tree")]
+    AccountKindMismatch,
+
+    /// The root a proof was built against has left the changelog buffer
+    #[error("The proof's root is no longer in the changelog buffer")]
+    StaleRoot,
 }
 
 impl From<MerkleTreeError> for ProgramError {
@@ -0,0 +1,789 @@
+//! Builders for every `MerkleInstruction`, with the accounts it expects.
+
+use solana_program::{
+    hash::Hash,
+    instruction::{AccountMeta, Instruction},
+    program_error::ProgramError,
+    pubkey::Pubkey,
+};
+
+use crate::{
+    instructions::MerkleInstruction,
+    mmr::MmrProof,
+    sparse::{SparseKey, SparseMerkleProof},
+    state::TreeConfig,
+    utils::MultiProof,
+};
+
+/// Creates a `BuildTree` instruction. `authority` must sign and becomes the
+/// tree's authority. The tree remembers its last `root_history_size` roots.
+pub fn build_tree<T: AsRef<[u8]>>(
+    program_id: &Pubkey,
+    tree: &Pubkey,
+    authority: &Pubkey,
+    config: TreeConfig,
+    leaves: &[T],
+    root_history_size: u8,
+) -> Result<Instruction, ProgramError> {
+    let instruction = MerkleInstruction::BuildTree {
+        config,
+        data: to_leaf_data(leaves),
+        root_history_size,
+    };
+    mutating(program_id, tree, authority, &instruction)
+}
+
+/// Creates a `BuildTreeFromHashes` instruction. `authority` must sign and
+/// becomes the tree's authority.
+pub fn build_tree_from_hashes(
+    program_id: &Pubkey,
+    tree: &Pubkey,
+    authority: &Pubkey,
+    config: TreeConfig,
+    leaves: &[Hash],
+    root_history_size: u8,
+) -> Result<Instruction, ProgramError> {
+    let instruction = MerkleInstruction::BuildTreeFromHashes {
+        config,
+        leaves: leaves.to_vec(),
+        root_history_size,
+    };
+    mutating(program_id, tree, authority, &instruction)
+}
+
+/// Creates a `BuildRootOnlyTree` instruction. `authority` must sign and
+/// becomes the tree's authority.
+#[allow(clippy::too_many_arguments)]
+pub fn build_root_only_tree(
+    program_id: &Pubkey,
+    tree: &Pubkey,
+    authority: &Pubkey,
+    config: TreeConfig,
+    leaves_prehashed: bool,
+    leaf_count: u32,
+    root: Hash,
+    root_history_size: u8,
+) -> Result<Instruction, ProgramError> {
+    let instruction = MerkleInstruction::BuildRootOnlyTree {
+        config,
+        leaves_prehashed,
+        leaf_count,
+        root,
+        root_history_size,
+    };
+    mutating(program_id, tree, authority, &instruction)
+}
+
+/// Creates a `BuildProof` instruction. The tree account is passed read-only.
+pub fn build_proof(
+    program_id: &Pubkey,
+    tree: &Pubkey,
+    leaf_index: u32,
+    log_proof: bool,
+) -> Result<Instruction, ProgramError> {
+    let instruction = MerkleInstruction::BuildProof {
+        leaf_index,
+        log_proof,
+    };
+    read_only(program_id, tree, &instruction)
+}
+
+/// Creates a `VerifyProof` instruction. The tree account is passed read-only.
+pub fn verify_proof(
+    program_id: &Pubkey,
+    tree: &Pubkey,
+    leaf_index: u32,
+    leaf_data: &[u8],
+    proof: &[Hash],
+) -> Result<Instruction, ProgramError> {
+    let instruction = MerkleInstruction::VerifyProof {
+        leaf_index,
+        leaf_data: leaf_data.to_vec(),
+        proof: proof.to_vec(),
+    };
+    read_only(program_id, tree, &instruction)
+}
+
+/// Creates a `VerifyProofForHash` instruction. The tree account is passed
+/// read-only.
+pub fn verify_proof_for_hash(
+    program_id: &Pubkey,
+    tree: &Pubkey,
+    leaf_index: u32,
+    leaf: Hash,
+    proof: &[Hash],
+) -> Result<Instruction, ProgramError> {
+    let instruction = MerkleInstruction::VerifyProofForHash {
+        leaf_index,
+        leaf,
+        proof: proof.to_vec(),
+    };
+    read_only(program_id, tree, &instruction)
+}
+
+/// Creates a `VerifyMultiProof` instruction from a multiproof whose leaves are
+/// given as raw data, in the order of `leaf_indices`. The tree account is
+/// passed read-only.
+pub fn verify_multiproof<T: AsRef<[u8]>>(
+    program_id: &Pubkey,
+    tree: &Pubkey,
+    leaf_indices: &[u32],
+    leaves: &[T],
+    proof: &[Hash],
+) -> Result<Instruction, ProgramError> {
+    let instruction = MerkleInstruction::VerifyMultiProof {
+        leaf_indices: leaf_indices.to_vec(),
+        leaf_data: to_leaf_data(leaves),
+        proof: proof.to_vec(),
+    };
+    read_only(program_id, tree, &instruction)
+}
+
+/// Creates a `VerifyMultiProofForHashes` instruction from a `MultiProof`. The
+/// tree account is passed read-only.
+pub fn verify_multiproof_for_hashes(
+    program_id: &Pubkey,
+    tree: &Pubkey,
+    proof: &MultiProof,
+) -> Result<Instruction, ProgramError> {
+    let instruction = MerkleInstruction::VerifyMultiProofForHashes {
+        leaf_indices: proof.leaf_indices.clone(),
+        leaves: proof.leaves.clone(),
+        proof: proof.siblings.clone(),
+    };
+    read_only(program_id, tree, &instruction)
+}
+
+/// Creates an `AppendLeaves` instruction signed by the tree's `authority`.
+pub fn append_leaves<T: AsRef<[u8]>>(
+    program_id: &Pubkey,
+    tree: &Pubkey,
+    authority: &Pubkey,
+    leaves: &[T],
+) -> Result<Instruction, ProgramError> {
+    let instruction = MerkleInstruction::AppendLeaves {
+        data: to_leaf_data(leaves),
+    };
+    mutating(program_id, tree, authority, &instruction)
+}
+
+/// Creates an `AppendLeafHashes` instruction signed by the tree's `authority`.
+pub fn append_leaf_hashes(
+    program_id: &Pubkey,
+    tree: &Pubkey,
+    authority: &Pubkey,
+    leaves: &[Hash],
+) -> Result<Instruction, ProgramError> {
+    let instruction = MerkleInstruction::AppendLeafHashes {
+        leaves: leaves.to_vec(),
+    };
+    mutating(program_id, tree, authority, &instruction)
+}
+
+/// Creates a `ReplaceLeaf` instruction signed by the tree's `authority`.
+pub fn replace_leaf(
+    program_id: &Pubkey,
+    tree: &Pubkey,
+    authority: &Pubkey,
+    index: u32,
+    old_leaf: Hash,
+    new_leaf_data: &[u8],
+    proof: &[Hash],
+) -> Result<Instruction, ProgramError> {
+    let instruction = MerkleInstruction::ReplaceLeaf {
+        index,
+        old_leaf,
+        new_leaf_data: new_leaf_data.to_vec(),
+        proof: proof.to_vec(),
+    };
+    mutating(program_id, tree, authority, &instruction)
+}
+
+/// Creates a `ReplaceLeafHash` instruction signed by the tree's `authority`.
+pub fn replace_leaf_hash(
+    program_id: &Pubkey,
+    tree: &Pubkey,
+    authority: &Pubkey,
+    index: u32,
+    old_leaf: Hash,
+    new_leaf: Hash,
+    proof: &[Hash],
+) -> Result<Instruction, ProgramError> {
+    let instruction = MerkleInstruction::ReplaceLeafHash {
+        index,
+        old_leaf,
+        new_leaf,
+        proof: proof.to_vec(),
+    };
+    mutating(program_id, tree, authority, &instruction)
+}
+
+/// Creates a `SetAuthority` instruction signed by the tree's current
+/// `authority`.
+pub fn set_authority(
+    program_id: &Pubkey,
+    tree: &Pubkey,
+    authority: &Pubkey,
+    new_authority: &Pubkey,
+) -> Result<Instruction, ProgramError> {
+    let instruction = MerkleInstruction::SetAuthority {
+        new_authority: *new_authority,
+    };
+    mutating(program_id, tree, authority, &instruction)
+}
+
+/// Creates a `BuildSparseTree` instruction. `authority` must sign and becomes
+/// the tree's authority.
+pub fn build_sparse_tree(
+    program_id: &Pubkey,
+    tree: &Pubkey,
+    authority: &Pubkey,
+    config: TreeConfig,
+) -> Result<Instruction, ProgramError> {
+    let instruction = MerkleInstruction::BuildSparseTree { config };
+    mutating(program_id, tree, authority, &instruction)
+}
+
+/// Creates a `SparseInsert` instruction signed by the tree's `authority`.
+pub fn sparse_insert(
+    program_id: &Pubkey,
+    tree: &Pubkey,
+    authority: &Pubkey,
+    key: &SparseKey,
+    value: &[u8],
+    proof: &SparseMerkleProof,
+) -> Result<Instruction, ProgramError> {
+    let instruction = MerkleInstruction::SparseInsert {
+        key: *key,
+        value: value.to_vec(),
+        proof: proof.clone(),
+    };
+    mutating(program_id, tree, authority, &instruction)
+}
+
+/// Creates a `SparseDelete` instruction signed by the tree's `authority`.
+pub fn sparse_delete(
+    program_id: &Pubkey,
+    tree: &Pubkey,
+    authority: &Pubkey,
+    key: &SparseKey,
+    value: &[u8],
+    proof: &SparseMerkleProof,
+) -> Result<Instruction, ProgramError> {
+    let instruction = MerkleInstruction::SparseDelete {
+        key: *key,
+        value: value.to_vec(),
+        proof: proof.clone(),
+    };
+    mutating(program_id, tree, authority, &instruction)
+}
+
+/// Creates a `VerifySparseProof` instruction. The tree account is passed
+/// read-only.
+pub fn verify_sparse_proof(
+    program_id: &Pubkey,
+    tree: &Pubkey,
+    key: &SparseKey,
+    value: Option<&[u8]>,
+    proof: &SparseMerkleProof,
+) -> Result<Instruction, ProgramError> {
+    let instruction = MerkleInstruction::VerifySparseProof {
+        key: *key,
+        value: value.map(<[u8]>::to_vec),
+        proof: proof.clone(),
+    };
+    read_only(program_id, tree, &instruction)
+}
+
+/// Creates a `BuildConcurrentTree` instruction. `authority` must sign and
+/// becomes the tree's authority.
+pub fn build_concurrent_tree(
+    program_id: &Pubkey,
+    tree: &Pubkey,
+    authority: &Pubkey,
+    config: TreeConfig,
+    max_depth: u8,
+    max_buffer_size: u32,
+) -> Result<Instruction, ProgramError> {
+    let instruction = MerkleInstruction::BuildConcurrentTree {
+        config,
+        max_depth,
+        max_buffer_size,
+    };
+    mutating(program_id, tree, authority, &instruction)
+}
+
+/// Creates an `AppendConcurrentLeaves` instruction signed by the tree's
+/// `authority`.
+pub fn append_concurrent_leaves<T: AsRef<[u8]>>(
+    program_id: &Pubkey,
+    tree: &Pubkey,
+    authority: &Pubkey,
+    leaves: &[T],
+) -> Result<Instruction, ProgramError> {
+    let instruction = MerkleInstruction::AppendConcurrentLeaves {
+        data: to_leaf_data(leaves),
+    };
+    mutating(program_id, tree, authority, &instruction)
+}
+
+/// Creates a `ReplaceConcurrentLeaf` instruction signed by the tree's
+/// `authority`.
+#[allow(clippy::too_many_arguments)]
+pub fn replace_concurrent_leaf(
+    program_id: &Pubkey,
+    tree: &Pubkey,
+    authority: &Pubkey,
+    root: Hash,
+    index: u32,
+    old_leaf: Hash,
+    new_leaf_data: &[u8],
+    proof: &[Hash],
+) -> Result<Instruction, ProgramError> {
+    let instruction = MerkleInstruction::ReplaceConcurrentLeaf {
+        root,
+        index,
+        old_leaf,
+        new_leaf_data: new_leaf_data.to_vec(),
+        proof: proof.to_vec(),
+    };
+    mutating(program_id, tree, authority, &instruction)
+}
+
+/// Creates a `BuildIncrementalTree` instruction. `authority` must sign and
+/// becomes the tree's authority.
+pub fn build_incremental_tree(
+    program_id: &Pubkey,
+    tree: &Pubkey,
+    authority: &Pubkey,
+    config: TreeConfig,
+    depth: u8,
+) -> Result<Instruction, ProgramError> {
+    let instruction = MerkleInstruction::BuildIncrementalTree { config, depth };
+    mutating(program_id, tree, authority, &instruction)
+}
+
+/// Creates an `AppendIncrementalLeaves` instruction signed by the tree's
+/// `authority`.
+pub fn append_incremental_leaves<T: AsRef<[u8]>>(
+    program_id: &Pubkey,
+    tree: &Pubkey,
+    authority: &Pubkey,
+    leaves: &[T],
+) -> Result<Instruction, ProgramError> {
+    let instruction = MerkleInstruction::AppendIncrementalLeaves {
+        data: to_leaf_data(leaves),
+    };
+    mutating(program_id, tree, authority, &instruction)
+}
+
+/// Creates a `VerifyIncrementalProof` instruction. The tree account is passed
+/// read-only.
+pub fn verify_incremental_proof(
+    program_id: &Pubkey,
+    tree: &Pubkey,
+    leaf_index: u32,
+    leaf_data: &[u8],
+    proof: &[Hash],
+) -> Result<Instruction, ProgramError> {
+    let instruction = MerkleInstruction::VerifyIncrementalProof {
+        leaf_index,
+        leaf_data: leaf_data.to_vec(),
+        proof: proof.to_vec(),
+    };
+    read_only(program_id, tree, &instruction)
+}
+
+/// Creates a `BuildMmr` instruction. `authority` must sign and becomes the
+/// range's authority.
+pub fn build_mmr(
+    program_id: &Pubkey,
+    mmr: &Pubkey,
+    authority: &Pubkey,
+    config: TreeConfig,
+) -> Result<Instruction, ProgramError> {
+    let instruction = MerkleInstruction::BuildMmr { config };
+    mutating(program_id, mmr, authority, &instruction)
+}
+
+/// Creates an `AppendMmrLeaves` instruction signed by the range's `authority`.
+pub fn append_mmr_leaves<T: AsRef<[u8]>>(
+    program_id: &Pubkey,
+    mmr: &Pubkey,
+    authority: &Pubkey,
+    leaves: &[T],
+) -> Result<Instruction, ProgramError> {
+    let instruction = MerkleInstruction::AppendMmrLeaves {
+        data: to_leaf_data(leaves),
+    };
+    mutating(program_id, mmr, authority, &instruction)
+}
+
+/// Creates a `VerifyMmrProof` instruction. The range account is passed
+/// read-only.
+pub fn verify_mmr_proof(
+    program_id: &Pubkey,
+    mmr: &Pubkey,
+    leaf_data: &[u8],
+    proof: &MmrProof,
+) -> Result<Instruction, ProgramError> {
+    let instruction = MerkleInstruction::VerifyMmrProof {
+        leaf_data: leaf_data.to_vec(),
+        proof: proof.clone(),
+    };
+    read_only(program_id, mmr, &instruction)
+}
+
+/// An instruction that only reads the tree.
+fn read_only(
+    program_id: &Pubkey,
+    tree: &Pubkey,
+    instruction: &MerkleInstruction,
+) -> Result<Instruction, ProgramError> {
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts: vec![AccountMeta::new_readonly(*tree, false)],
+        data: instruction.pack()?,
+    })
+}
+
+/// An instruction that writes the tree on behalf of its signing authority.
+fn mutating(
+    program_id: &Pubkey,
+    tree: &Pubkey,
+    authority: &Pubkey,
+    instruction: &MerkleInstruction,
+) -> Result<Instruction, ProgramError> {
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts: vec![
+            AccountMeta::new(*tree, false),
+            AccountMeta::new_readonly(*authority, true),
+        ],
+        data: instruction.pack()?,
+    })
+}
+
+fn to_leaf_data<T: AsRef<[u8]>>(leaves: &[T]) -> Vec<Vec<u8>> {
+    leaves.iter().map(|leaf| leaf.as_ref().to_vec()).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Keys {
+        program_id: Pubkey,
+        tree: Pubkey,
+        authority: Pubkey,
+    }
+
+    impl Keys {
+        fn new() -> Self {
+            Keys {
+                program_id: Pubkey::new_unique(),
+                tree: Pubkey::new_unique(),
+                authority: Pubkey::new_unique(),
+            }
+        }
+
+        /// Checks that `instruction` decodes to `expected` and writes the tree
+        /// with the authority's signature.
+        fn assert_mutating(&self, instruction: Instruction, expected: MerkleInstruction) {
+            assert_eq!(instruction.program_id, self.program_id);
+            assert_eq!(
+                instruction.accounts,
+                vec![
+                    AccountMeta::new(self.tree, false),
+                    AccountMeta::new_readonly(self.authority, true),
+                ]
+            );
+            assert_eq!(MerkleInstruction::unpack(&instruction.data), Ok(expected));
+        }
+
+        /// Checks that `instruction` decodes to `expected` and only reads the
+        /// tree.
+        fn assert_read_only(&self, instruction: Instruction, expected: MerkleInstruction) {
+            assert_eq!(instruction.program_id, self.program_id);
+            assert_eq!(
+                instruction.accounts,
+                vec![AccountMeta::new_readonly(self.tree, false)]
+            );
+            assert_eq!(MerkleInstruction::unpack(&instruction.data), Ok(expected));
+        }
+    }
+
+    fn config() -> TreeConfig {
+        TreeConfig {
+            domain_separated: true,
+            ..TreeConfig::default()
+        }
+    }
+
+    #[test]
+    fn tree_builders_round_trip() {
+        let k = Keys::new();
+        let (root, leaf) = (Hash::new_unique(), Hash::new_unique());
+
+        k.assert_mutating(
+            build_tree(
+                &k.program_id,
+                &k.tree,
+                &k.authority,
+                config(),
+                &["a", "bc"],
+                4,
+            )
+            .unwrap(),
+            MerkleInstruction::BuildTree {
+                config: config(),
+                data: vec![b"a".to_vec(), b"bc".to_vec()],
+                root_history_size: 4,
+            },
+        );
+        k.assert_mutating(
+            build_tree_from_hashes(&k.program_id, &k.tree, &k.authority, config(), &[leaf], 2)
+                .unwrap(),
+            MerkleInstruction::BuildTreeFromHashes {
+                config: config(),
+                leaves: vec![leaf],
+                root_history_size: 2,
+            },
+        );
+        k.assert_mutating(
+            build_root_only_tree(
+                &k.program_id,
+                &k.tree,
+                &k.authority,
+                config(),
+                true,
+                9,
+                root,
+                1,
+            )
+            .unwrap(),
+            MerkleInstruction::BuildRootOnlyTree {
+                config: config(),
+                leaves_prehashed: true,
+                leaf_count: 9,
+                root,
+                root_history_size: 1,
+            },
+        );
+        k.assert_mutating(
+            append_leaves(&k.program_id, &k.tree, &k.authority, &[[7u8; 3]]).unwrap(),
+            MerkleInstruction::AppendLeaves {
+                data: vec![vec![7; 3]],
+            },
+        );
+        k.assert_mutating(
+            append_leaf_hashes(&k.program_id, &k.tree, &k.authority, &[leaf]).unwrap(),
+            MerkleInstruction::AppendLeafHashes { leaves: vec![leaf] },
+        );
+        k.assert_mutating(
+            replace_leaf(
+                &k.program_id,
+                &k.tree,
+                &k.authority,
+                3,
+                leaf,
+                b"new",
+                &[root],
+            )
+            .unwrap(),
+            MerkleInstruction::ReplaceLeaf {
+                index: 3,
+                old_leaf: leaf,
+                new_leaf_data: b"new".to_vec(),
+                proof: vec![root],
+            },
+        );
+        k.assert_mutating(
+            replace_leaf_hash(&k.program_id, &k.tree, &k.authority, 3, leaf, root, &[]).unwrap(),
+            MerkleInstruction::ReplaceLeafHash {
+                index: 3,
+                old_leaf: leaf,
+                new_leaf: root,
+                proof: Vec::new(),
+            },
+        );
+        let new_authority = Pubkey::new_unique();
+        k.assert_mutating(
+            set_authority(&k.program_id, &k.tree, &k.authority, &new_authority).unwrap(),
+            MerkleInstruction::SetAuthority { new_authority },
+        );
+    }
+
+    #[test]
+    fn proof_builders_round_trip() {
+        let k = Keys::new();
+        let (leaf, sibling) = (Hash::new_unique(), Hash::new_unique());
+
+        k.assert_read_only(
+            build_proof(&k.program_id, &k.tree, 5, true).unwrap(),
+            MerkleInstruction::BuildProof {
+                leaf_index: 5,
+                log_proof: true,
+            },
+        );
+        k.assert_read_only(
+            verify_proof(&k.program_id, &k.tree, 5, b"leaf", &[sibling]).unwrap(),
+            MerkleInstruction::VerifyProof {
+                leaf_index: 5,
+                leaf_data: b"leaf".to_vec(),
+                proof: vec![sibling],
+            },
+        );
+        k.assert_read_only(
+            verify_proof_for_hash(&k.program_id, &k.tree, 5, leaf, &[sibling]).unwrap(),
+            MerkleInstruction::VerifyProofForHash {
+                leaf_index: 5,
+                leaf,
+                proof: vec![sibling],
+            },
+        );
+        k.assert_read_only(
+            verify_multiproof(&k.program_id, &k.tree, &[0, 2], &["a", "c"], &[sibling]).unwrap(),
+            MerkleInstruction::VerifyMultiProof {
+                leaf_indices: vec![0, 2],
+                leaf_data: vec![b"a".to_vec(), b"c".to_vec()],
+                proof: vec![sibling],
+            },
+        );
+        let multiproof = MultiProof {
+            leaf_indices: vec![1],
+            leaves: vec![leaf],
+            siblings: vec![sibling],
+        };
+        k.assert_read_only(
+            verify_multiproof_for_hashes(&k.program_id, &k.tree, &multiproof).unwrap(),
+            MerkleInstruction::VerifyMultiProofForHashes {
+                leaf_indices: vec![1],
+                leaves: vec![leaf],
+                proof: vec![sibling],
+            },
+        );
+    }
+
+    #[test]
+    fn sparse_builders_round_trip() {
+        let k = Keys::new();
+        let key = [3; 32];
+        let proof = SparseMerkleProof {
+            bitmap: [1; 32],
+            siblings: vec![Hash::new_unique()],
+        };
+
+        k.assert_mutating(
+            build_sparse_tree(&k.program_id, &k.tree, &k.authority, config()).unwrap(),
+            MerkleInstruction::BuildSparseTree { config: config() },
+        );
+        k.assert_mutating(
+            sparse_insert(&k.program_id, &k.tree, &k.authority, &key, b"v", &proof).unwrap(),
+            MerkleInstruction::SparseInsert {
+                key,
+                value: b"v".to_vec(),
+                proof: proof.clone(),
+            },
+        );
+        k.assert_mutating(
+            sparse_delete(&k.program_id, &k.tree, &k.authority, &key, b"v", &proof).unwrap(),
+            MerkleInstruction::SparseDelete {
+                key,
+                value: b"v".to_vec(),
+                proof: proof.clone(),
+            },
+        );
+        k.assert_read_only(
+            verify_sparse_proof(&k.program_id, &k.tree, &key, None, &proof).unwrap(),
+            MerkleInstruction::VerifySparseProof {
+                key,
+                value: None,
+                proof,
+            },
+        );
+    }
+
+    #[test]
+    fn append_only_tree_builders_round_trip() {
+        let k = Keys::new();
+        let (root, leaf) = (Hash::new_unique(), Hash::new_unique());
+
+        k.assert_mutating(
+            build_concurrent_tree(&k.program_id, &k.tree, &k.authority, config(), 14, 64).unwrap(),
+            MerkleInstruction::BuildConcurrentTree {
+                config: config(),
+                max_depth: 14,
+                max_buffer_size: 64,
+            },
+        );
+        k.assert_mutating(
+            append_concurrent_leaves(&k.program_id, &k.tree, &k.authority, &["a"]).unwrap(),
+            MerkleInstruction::AppendConcurrentLeaves {
+                data: vec![b"a".to_vec()],
+            },
+        );
+        k.assert_mutating(
+            replace_concurrent_leaf(
+                &k.program_id,
+                &k.tree,
+                &k.authority,
+                root,
+                2,
+                leaf,
+                b"b",
+                &[root],
+            )
+            .unwrap(),
+            MerkleInstruction::ReplaceConcurrentLeaf {
+                root,
+                index: 2,
+                old_leaf: leaf,
+                new_leaf_data: b"b".to_vec(),
+                proof: vec![root],
+            },
+        );
+        k.assert_mutating(
+            build_incremental_tree(&k.program_id, &k.tree, &k.authority, config(), 20).unwrap(),
+            MerkleInstruction::BuildIncrementalTree {
+                config: config(),
+                depth: 20,
+            },
+        );
+        k.assert_mutating(
+            append_incremental_leaves(&k.program_id, &k.tree, &k.authority, &["a"]).unwrap(),
+            MerkleInstruction::AppendIncrementalLeaves {
+                data: vec![b"a".to_vec()],
+            },
+        );
+        k.assert_read_only(
+            verify_incremental_proof(&k.program_id, &k.tree, 1, b"a", &[leaf]).unwrap(),
+            MerkleInstruction::VerifyIncrementalProof {
+                leaf_index: 1,
+                leaf_data: b"a".to_vec(),
+                proof: vec![leaf],
+            },
+        );
+        k.assert_mutating(
+            build_mmr(&k.program_id, &k.tree, &k.authority, config()).unwrap(),
+            MerkleInstruction::BuildMmr { config: config() },
+        );
+        k.assert_mutating(
+            append_mmr_leaves(&k.program_id, &k.tree, &k.authority, &["a"]).unwrap(),
+            MerkleInstruction::AppendMmrLeaves {
+                data: vec![b"a".to_vec()],
+            },
+        );
+        let proof = MmrProof {
+            leaf_index: 2,
+            siblings: vec![leaf],
+        };
+        k.assert_read_only(
+            verify_mmr_proof(&k.program_id, &k.tree, b"c", &proof).unwrap(),
+            MerkleInstruction::VerifyMmrProof {
+                leaf_data: b"c".to_vec(),
+                proof,
+            },
+        );
+    }
+}
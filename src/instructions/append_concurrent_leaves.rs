@@ -0,0 +1,91 @@
+use crate::{
+    errors::MerkleTreeError, instructions::check_authority, state::ConcurrentMerkleTreeAccount,
+    utils::MerkleHasher,
+};
+use solana_program::{
+    account_info::{next_account_info, AccountInfo},
+    entrypoint::ProgramResult,
+    program_error::ProgramError,
+    pubkey::Pubkey,
+};
+
+/// Hashes new leaves and appends them to a concurrent tree, recording one
+/// change per leaf.
+pub fn process_append_concurrent_leaves(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    leaves: Vec<Vec<u8>>,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let tree_account_info = next_account_info(account_info_iter)?;
+    let authority_info = next_account_info(account_info_iter)?;
+
+    // Ensure this account is owned by the program
+    if tree_account_info.owner != program_id {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+
+    if leaves.is_empty() {
+        return Err(MerkleTreeError::InvalidInstructionData.into());
+    }
+
+    // Load the concurrent tree account.
+    let mut data = tree_account_info.try_borrow_mut_data()?;
+    let mut tree_account = ConcurrentMerkleTreeAccount::unpack(&data)?;
+    check_authority(&tree_account.authority, authority_info)?;
+
+    // Hash the new leaves with the tree's scheme and append them one by one
+    let config = tree_account.config;
+    for leaf in &leaves {
+        tree_account.append(&config, &mut data, config.hash_leaf(leaf))?;
+    }
+
+    // Pack the updated ConcurrentMerkleTreeAccount header back into the account
+    ConcurrentMerkleTreeAccount::pack(tree_account, &mut data)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        instructions::{
+            build_concurrent_tree::process_build_concurrent_tree, test_accounts::TestAccount,
+        },
+        merkle_tree::MerkleTree,
+        state::TreeConfig,
+    };
+
+    #[test]
+    fn appends_until_the_tree_is_full() {
+        let program_id = Pubkey::new_unique();
+        let config = TreeConfig::default();
+        let mut tree = TestAccount::new(program_id, ConcurrentMerkleTreeAccount::space_for(2, 2));
+        let mut authority = TestAccount::signer();
+        process_build_concurrent_tree(&program_id, &[tree.info(), authority.info()], config, 2, 2)
+            .unwrap();
+
+        let leaves = (0..4u8).map(|i| vec![i]).collect::<Vec<_>>();
+        process_append_concurrent_leaves(
+            &program_id,
+            &[tree.info(), authority.info()],
+            leaves.clone(),
+        )
+        .unwrap();
+        let tree_account = ConcurrentMerkleTreeAccount::unpack(&tree.data).unwrap();
+        assert_eq!(tree_account.rightmost_index, 4);
+        assert_eq!(
+            tree_account.root,
+            MerkleTree::new(config, &leaves).unwrap().root()
+        );
+
+        assert_eq!(
+            process_append_concurrent_leaves(
+                &program_id,
+                &[tree.info(), authority.info()],
+                vec![vec![4]],
+            ),
+            Err(MerkleTreeError::TreeCapacityExceeded.into())
+        );
+    }
+}
@@ -0,0 +1,51 @@
+use crate::{
+    errors::MerkleTreeError,
+    instructions::check_authority,
+    state::IncrementalMerkleTreeAccount,
+    utils::{empty_subtree_hashes, MerkleHasher},
+};
+use solana_program::{
+    account_info::{next_account_info, AccountInfo},
+    entrypoint::ProgramResult,
+    program_error::ProgramError,
+    pubkey::Pubkey,
+};
+
+/// Hashes new leaves and appends them to an incremental tree.
+pub fn process_append_incremental_leaves(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    leaves: Vec<Vec<u8>>,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let tree_account_info = next_account_info(account_info_iter)?;
+    let authority_info = next_account_info(account_info_iter)?;
+
+    // Ensure this account is owned by the program
+    if tree_account_info.owner != program_id {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+
+    if leaves.is_empty() {
+        return Err(MerkleTreeError::InvalidInstructionData.into());
+    }
+
+    // Load the incremental tree account.
+    let mut tree_account =
+        IncrementalMerkleTreeAccount::unpack(&tree_account_info.try_borrow_data()?)?;
+    check_authority(&tree_account.authority, authority_info)?;
+
+    // Hash the new leaves with the tree's scheme and append them one by one
+    let config = tree_account.config;
+    let empty_hashes = empty_subtree_hashes(&config, tree_account.depth as usize);
+    for leaf in &leaves {
+        tree_account.append(&config, &empty_hashes, config.hash_leaf(leaf))?;
+    }
+
+    // Pack the updated IncrementalMerkleTreeAccount data back into the account
+    IncrementalMerkleTreeAccount::pack(
+        tree_account,
+        &mut tree_account_info.try_borrow_mut_data()?,
+    )?;
+    Ok(())
+}
@@ -0,0 +1,195 @@
+use crate::{
+    errors::MerkleTreeError,
+    instructions::{check_authority, check_leaf_encoding, LeafInput},
+    state::{MerkleTreeAccount, TreeStorage},
+    utils::build_merkle_root,
+};
+use solana_program::{
+    account_info::{next_account_info, AccountInfo},
+    entrypoint::ProgramResult,
+    program_error::ProgramError,
+    pubkey::Pubkey,
+};
+
+/// Hashes new leaves, appends them to the stored leaves and recomputes the root.
+pub fn process_append_leaves(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    leaves: Vec<LeafInput>,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let tree_account_info = next_account_info(account_info_iter)?;
+    let authority_info = next_account_info(account_info_iter)?;
+
+    // Ensure this account is owned by the program
+    if tree_account_info.owner != program_id {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+
+    if leaves.is_empty() {
+        return Err(MerkleTreeError::InvalidInstructionData.into());
+    }
+
+    // Load the Merkle tree account.
+    let mut tree_account = MerkleTreeAccount::unpack(&tree_account_info.try_borrow_data()?)?;
+    check_authority(&tree_account.authority, authority_info)?;
+    if tree_account.storage == TreeStorage::RootOnly {
+        return Err(MerkleTreeError::LeavesNotStored.into());
+    }
+    for leaf in &leaves {
+        check_leaf_encoding(&tree_account, leaf)?;
+    }
+
+    // Fail before hashing anything if the new leaves cannot be stored.
+    let leaf_count = tree_account.leaves.len() + leaves.len();
+    let capacity =
+        MerkleTreeAccount::capacity(tree_account_info.data_len(), tree_account.root_history_size);
+    if leaf_count > capacity {
+        return Err(MerkleTreeError::TreeCapacityExceeded.into());
+    }
+
+    // Hash the new leaves with the tree's scheme and append them
+    let config = tree_account.config;
+    tree_account
+        .leaves
+        .extend(leaves.iter().map(|leaf| leaf.to_hash(&config)));
+
+    // Rebuild the root over the full leaf set, keeping the previous one
+    let root = build_merkle_root(&config, &tree_account.leaves)?;
+    let leaf_count =
+        u32::try_from(leaf_count).map_err(|_| MerkleTreeError::TreeCapacityExceeded)?;
+    tree_account.set_root(root, leaf_count);
+
+    // Pack the updated MerkleTreeAccount data back into the account
+    MerkleTreeAccount::pack(tree_account, &mut tree_account_info.try_borrow_mut_data()?)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        instructions::{
+            build_tree::{process_build_root_only_tree, process_build_tree},
+            test_accounts::TestAccount,
+        },
+        state::TreeConfig,
+        utils::MerkleHasher,
+    };
+
+    fn leaves(range: std::ops::Range<usize>) -> Vec<LeafInput> {
+        range
+            .map(|i| LeafInput::Data(format!("leaf {i}").into_bytes()))
+            .collect()
+    }
+
+    #[test]
+    fn appends_up_to_the_account_capacity() {
+        let program_id = Pubkey::new_unique();
+        let root_history_size = 3;
+        let capacity = 5;
+        let mut tree = TestAccount::new(
+            program_id,
+            MerkleTreeAccount::space_for(capacity, root_history_size),
+        );
+        let mut authority = TestAccount::signer();
+        process_build_tree(
+            &program_id,
+            &[tree.info(), authority.info()],
+            TreeConfig::default(),
+            leaves(0..2),
+            root_history_size,
+        )
+        .unwrap();
+
+        process_append_leaves(
+            &program_id,
+            &[tree.info(), authority.info()],
+            leaves(2..capacity),
+        )
+        .unwrap();
+        let account = MerkleTreeAccount::unpack(&tree.data).unwrap();
+        assert_eq!(account.leaf_count, capacity as u32);
+        assert_eq!(account.root_history.len(), 1);
+
+        assert_eq!(
+            process_append_leaves(
+                &program_id,
+                &[tree.info(), authority.info()],
+                leaves(capacity..capacity + 1),
+            ),
+            Err(MerkleTreeError::TreeCapacityExceeded.into())
+        );
+    }
+
+    #[test]
+    fn only_the_signing_authority_may_append() {
+        let program_id = Pubkey::new_unique();
+        let mut tree = TestAccount::new(program_id, MerkleTreeAccount::space_for(2, 0));
+        let mut authority = TestAccount::signer();
+        let mut intruder = TestAccount::signer();
+        process_build_tree(
+            &program_id,
+            &[tree.info(), authority.info()],
+            TreeConfig::default(),
+            leaves(0..1),
+            0,
+        )
+        .unwrap();
+        let before = tree.data.clone();
+
+        assert_eq!(
+            process_append_leaves(&program_id, &[tree.info(), intruder.info()], leaves(1..2)),
+            Err(MerkleTreeError::Unauthorized.into())
+        );
+        authority.is_signer = false;
+        assert_eq!(
+            process_append_leaves(&program_id, &[tree.info(), authority.info()], leaves(1..2)),
+            Err(MerkleTreeError::Unauthorized.into())
+        );
+        assert_eq!(tree.data, before);
+    }
+
+    #[test]
+    fn leaves_must_be_encoded_like_the_stored_ones() {
+        let program_id = Pubkey::new_unique();
+        let mut tree = TestAccount::new(program_id, MerkleTreeAccount::space_for(2, 0));
+        let mut authority = TestAccount::signer();
+        let leaf = LeafInput::Hash(TreeConfig::default().hash_leaf(b"leaf 0"));
+        process_build_tree(
+            &program_id,
+            &[tree.info(), authority.info()],
+            TreeConfig::default(),
+            vec![leaf],
+            0,
+        )
+        .unwrap();
+
+        assert_eq!(
+            process_append_leaves(&program_id, &[tree.info(), authority.info()], leaves(1..2)),
+            Err(MerkleTreeError::LeafEncodingMismatch.into())
+        );
+    }
+
+    #[test]
+    fn root_only_trees_have_no_leaves_to_append_to() {
+        let program_id = Pubkey::new_unique();
+        let mut tree = TestAccount::new(program_id, MerkleTreeAccount::space_for(0, 0));
+        let mut authority = TestAccount::signer();
+        process_build_root_only_tree(
+            &program_id,
+            &[tree.info(), authority.info()],
+            TreeConfig::default(),
+            false,
+            1,
+            TreeConfig::default().hash_leaf(b"leaf 0"),
+            0,
+        )
+        .unwrap();
+
+        assert_eq!(
+            process_append_leaves(&program_id, &[tree.info(), authority.info()], leaves(1..2)),
+            Err(MerkleTreeError::LeavesNotStored.into())
+        );
+    }
+}
@@ -0,0 +1,44 @@
+use crate::{
+    errors::MerkleTreeError, instructions::check_authority, state::MerkleMountainRangeAccount,
+    utils::MerkleHasher,
+};
+use solana_program::{
+    account_info::{next_account_info, AccountInfo},
+    entrypoint::ProgramResult,
+    program_error::ProgramError,
+    pubkey::Pubkey,
+};
+
+/// Hashes new leaves and appends them to a Merkle mountain range.
+pub fn process_append_mmr_leaves(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    leaves: Vec<Vec<u8>>,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let mmr_account_info = next_account_info(account_info_iter)?;
+    let authority_info = next_account_info(account_info_iter)?;
+
+    // Ensure this account is owned by the program
+    if mmr_account_info.owner != program_id {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+
+    if leaves.is_empty() {
+        return Err(MerkleTreeError::InvalidInstructionData.into());
+    }
+
+    // Load the Merkle mountain range account.
+    let mut mmr_account = MerkleMountainRangeAccount::unpack(&mmr_account_info.try_borrow_data()?)?;
+    check_authority(&mmr_account.authority, authority_info)?;
+
+    // Hash the new leaves with the range's scheme and append them one by one
+    let config = mmr_account.config;
+    for leaf in &leaves {
+        mmr_account.append(&config, config.hash_leaf(leaf))?;
+    }
+
+    // Pack the updated MerkleMountainRangeAccount data back into the account
+    MerkleMountainRangeAccount::pack(mmr_account, &mut mmr_account_info.try_borrow_mut_data()?)?;
+    Ok(())
+}
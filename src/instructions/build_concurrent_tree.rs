@@ -0,0 +1,65 @@
+use crate::{
+    errors::MerkleTreeError,
+    state::{
+        ConcurrentMerkleTreeAccount, TreeConfig, CURRENT_CONCURRENT_ACCOUNT_VERSION,
+        MAX_CONCURRENT_TREE_DEPTH,
+    },
+};
+use solana_program::{
+    account_info::{next_account_info, AccountInfo},
+    entrypoint::ProgramResult,
+    program_error::ProgramError,
+    pubkey::Pubkey,
+};
+
+/// Initializes an empty concurrent Merkle tree.
+pub fn process_build_concurrent_tree(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    config: TreeConfig,
+    max_depth: u8,
+    max_buffer_size: u32,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let tree_account_info = next_account_info(account_info_iter)?;
+    let authority_info = next_account_info(account_info_iter)?;
+
+    // Ensure this account is owned by the program
+    if tree_account_info.owner != program_id {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+
+    // The future authority has to agree to own the tree
+    if !authority_info.is_signer {
+        return Err(MerkleTreeError::Unauthorized.into());
+    }
+
+    if max_depth == 0 || max_depth > MAX_CONCURRENT_TREE_DEPTH || max_buffer_size == 0 {
+        return Err(MerkleTreeError::InvalidInstructionData.into());
+    }
+
+    let mut data = tree_account_info.try_borrow_mut_data()?;
+
+    // Unpack the existing ConcurrentMerkleTreeAccount (not yet initialized)
+    let mut tree_account = ConcurrentMerkleTreeAccount::unpack_unchecked(&data)?;
+
+    if tree_account.is_initialized {
+        return Err(MerkleTreeError::AccountAlreadyInitialized.into());
+    }
+    if data.len() < ConcurrentMerkleTreeAccount::space_for(max_depth, max_buffer_size) {
+        return Err(ProgramError::AccountDataTooSmall);
+    }
+
+    // Set fields, record the empty tree as the first change and mark as initialized
+    tree_account.is_initialized = true;
+    tree_account.version = CURRENT_CONCURRENT_ACCOUNT_VERSION;
+    tree_account.config = config;
+    tree_account.max_depth = max_depth;
+    tree_account.max_buffer_size = max_buffer_size;
+    tree_account.authority = *authority_info.key;
+    tree_account.initialize_empty(&config, &mut data)?;
+
+    // Pack the updated ConcurrentMerkleTreeAccount header back into the account
+    ConcurrentMerkleTreeAccount::pack(tree_account, &mut data)?;
+    Ok(())
+}
@@ -0,0 +1,51 @@
+use crate::{
+    errors::MerkleTreeError,
+    instructions::build_tree::load_uninitialized,
+    state::{
+        IncrementalMerkleTreeAccount, TreeConfig, CURRENT_INCREMENTAL_ACCOUNT_VERSION,
+        MAX_INCREMENTAL_TREE_DEPTH,
+    },
+};
+use solana_program::{
+    account_info::{next_account_info, AccountInfo},
+    entrypoint::ProgramResult,
+    pubkey::Pubkey,
+};
+
+/// Initializes an empty incremental Merkle tree.
+pub fn process_build_incremental_tree(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    config: TreeConfig,
+    depth: u8,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let tree_account_info = next_account_info(account_info_iter)?;
+    let authority_info = next_account_info(account_info_iter)?;
+
+    let mut tree_account = load_uninitialized(
+        program_id,
+        tree_account_info,
+        authority_info,
+        IncrementalMerkleTreeAccount::unpack_unchecked,
+    )?;
+
+    if depth == 0 || depth > MAX_INCREMENTAL_TREE_DEPTH {
+        return Err(MerkleTreeError::InvalidInstructionData.into());
+    }
+
+    // Set fields, start from the empty tree and mark as initialized
+    tree_account.is_initialized = true;
+    tree_account.version = CURRENT_INCREMENTAL_ACCOUNT_VERSION;
+    tree_account.config = config;
+    tree_account.depth = depth;
+    tree_account.authority = *authority_info.key;
+    tree_account.initialize_empty(&config);
+
+    // Pack the updated IncrementalMerkleTreeAccount data back into the account
+    IncrementalMerkleTreeAccount::pack(
+        tree_account,
+        &mut tree_account_info.try_borrow_mut_data()?,
+    )?;
+    Ok(())
+}
@@ -0,0 +1,41 @@
+use crate::{
+    instructions::build_tree::load_uninitialized,
+    mmr::bag_peaks,
+    state::{MerkleMountainRangeAccount, TreeConfig, CURRENT_MMR_ACCOUNT_VERSION},
+};
+use solana_program::{
+    account_info::{next_account_info, AccountInfo},
+    entrypoint::ProgramResult,
+    pubkey::Pubkey,
+};
+
+/// Initializes an empty Merkle mountain range.
+pub fn process_build_mmr(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    config: TreeConfig,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let mmr_account_info = next_account_info(account_info_iter)?;
+    let authority_info = next_account_info(account_info_iter)?;
+
+    let mut mmr_account = load_uninitialized(
+        program_id,
+        mmr_account_info,
+        authority_info,
+        MerkleMountainRangeAccount::unpack_unchecked,
+    )?;
+
+    // Set fields and mark as initialized; the range starts without peaks
+    mmr_account.is_initialized = true;
+    mmr_account.version = CURRENT_MMR_ACCOUNT_VERSION;
+    mmr_account.config = config;
+    mmr_account.authority = *authority_info.key;
+    mmr_account.leaf_count = 0;
+    mmr_account.peaks = Vec::new();
+    mmr_account.root = bag_peaks(&config, &mmr_account.peaks);
+
+    // Pack the updated MerkleMountainRangeAccount data back into the account
+    MerkleMountainRangeAccount::pack(mmr_account, &mut mmr_account_info.try_borrow_mut_data()?)?;
+    Ok(())
+}
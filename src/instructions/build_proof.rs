@@ -2,18 +2,23 @@ use solana_program::{
     account_info::{next_account_info, AccountInfo},
     entrypoint::ProgramResult,
     msg,
+    program::set_return_data,
     program_error::ProgramError,
-    program_pack::Pack,
     pubkey::Pubkey,
 };
 
-use crate::{errors::MerkleTreeError, state::MerkleTreeAccount, utils::build_proof};
+use crate::{
+    errors::MerkleTreeError,
+    state::{MerkleTreeAccount, TreeStorage},
+    utils::{build_proof, MerkleProof},
+};
 
-/// Computes a Merkle proof for a given leaf index and returns it via logs.
+/// Computes a Merkle proof for a given leaf index and returns it via return data.
 pub fn process_build_proof(
     program_id: &Pubkey,
     accounts: &[AccountInfo],
     leaf_index: u32,
+    log_proof: bool,
 ) -> ProgramResult {
     let account_info_iter = &mut accounts.iter();
     let merkle_account_info = next_account_info(account_info_iter)?;
@@ -26,18 +31,70 @@ pub fn process_build_proof(
     // Load the Merkle tree account.
     let tree_account = MerkleTreeAccount::unpack(&merkle_account_info.try_borrow_data()?)?;
 
+    // Root-only trees have no leaves to build a proof from.
+    if tree_account.storage == TreeStorage::RootOnly {
+        return Err(MerkleTreeError::LeavesNotStored.into());
+    }
+
     // Ensure the `leaf_index` is within bounds.
-    if leaf_index as usize >= tree_account.leaves.len() {
+    if leaf_index >= tree_account.leaf_count {
         return Err(MerkleTreeError::InvalidLeafIndex.into());
     }
 
-    // Generate the proof using the stored leaves.
-    let proof = build_proof(leaf_index as usize, &tree_account.leaves)?;
+    // Generate the proof using the stored leaves and hashing scheme.
+    let siblings = build_proof(
+        &tree_account.config,
+        leaf_index as usize,
+        &tree_account.leaves,
+    )?;
 
-    // Log the proof so the client can extract it.
-    for hash in &proof {
-        msg!("Proof: {:?}", hash.to_bytes());
+    // Optionally log the proof for debugging.
+    if log_proof {
+        for hash in &siblings {
+            msg!("Proof: {:?}", hash.to_bytes());
+        }
     }
 
+    // Return the proof so simulation and CPI callers can consume it.
+    let proof = MerkleProof {
+        leaf_index,
+        leaf: tree_account.leaves[leaf_index as usize],
+        siblings,
+    };
+    set_return_data(&borsh::to_vec(&proof).map_err(|e| ProgramError::BorshIoError(e.to_string()))?);
+
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        instructions::{build_tree::process_build_root_only_tree, test_accounts::TestAccount},
+        merkle_tree::MerkleTree,
+        state::TreeConfig,
+    };
+
+    #[test]
+    fn root_only_trees_have_no_leaves_to_prove() {
+        let program_id = Pubkey::new_unique();
+        let off_chain = MerkleTree::new(TreeConfig::default(), &[b"a", b"b"]).unwrap();
+        let mut tree = TestAccount::new(program_id, MerkleTreeAccount::space_for(0, 0));
+        let mut authority = TestAccount::signer();
+        process_build_root_only_tree(
+            &program_id,
+            &[tree.info(), authority.info()],
+            TreeConfig::default(),
+            false,
+            off_chain.leaf_count(),
+            off_chain.root(),
+            0,
+        )
+        .unwrap();
+
+        assert_eq!(
+            process_build_proof(&program_id, &[tree.info()], 0, false),
+            Err(MerkleTreeError::LeavesNotStored.into())
+        );
+    }
+}
@@ -0,0 +1,78 @@
+use crate::{
+    errors::MerkleTreeError,
+    sparse::{check_sparse_config, SPARSE_TREE_DEPTH},
+    state::{SparseMerkleTreeAccount, TreeConfig, CURRENT_SPARSE_ACCOUNT_VERSION},
+    utils::empty_subtree_hashes,
+};
+use solana_program::{
+    account_info::{next_account_info, AccountInfo},
+    entrypoint::ProgramResult,
+    program_error::ProgramError,
+    pubkey::Pubkey,
+};
+
+/// Initializes an empty sparse Merkle tree.
+pub fn process_build_sparse_tree(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    config: TreeConfig,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let tree_account_info = next_account_info(account_info_iter)?;
+    let authority_info = next_account_info(account_info_iter)?;
+
+    // Ensure this account is owned by the program
+    if tree_account_info.owner != program_id {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+
+    // The future authority has to agree to own the tree
+    if !authority_info.is_signer {
+        return Err(MerkleTreeError::Unauthorized.into());
+    }
+
+    check_sparse_config(&config)?;
+
+    // Unpack the existing SparseMerkleTreeAccount (not yet initialized)
+    let mut tree_account =
+        SparseMerkleTreeAccount::unpack_unchecked(&tree_account_info.try_borrow_data()?)?;
+
+    if tree_account.is_initialized {
+        return Err(MerkleTreeError::AccountAlreadyInitialized.into());
+    }
+
+    // Set fields and mark as initialized; the root is that of an empty tree
+    tree_account.is_initialized = true;
+    tree_account.version = CURRENT_SPARSE_ACCOUNT_VERSION;
+    tree_account.config = config;
+    tree_account.entry_count = 0;
+    tree_account.authority = *authority_info.key;
+    tree_account.root = empty_subtree_hashes(&config, SPARSE_TREE_DEPTH)[SPARSE_TREE_DEPTH];
+
+    // Pack the updated SparseMerkleTreeAccount data back into the account
+    SparseMerkleTreeAccount::pack(tree_account, &mut tree_account_info.try_borrow_mut_data()?)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{instructions::test_accounts::TestAccount, state::NodeHashing};
+
+    #[test]
+    fn rejects_sorted_pair_hashing() {
+        let program_id = Pubkey::new_unique();
+        let mut tree = TestAccount::new(program_id, SparseMerkleTreeAccount::LEN);
+        let mut authority = TestAccount::signer();
+        let config = TreeConfig {
+            node_hashing: NodeHashing::SortedPair,
+            ..TreeConfig::default()
+        };
+
+        assert_eq!(
+            process_build_sparse_tree(&program_id, &[tree.info(), authority.info()], config),
+            Err(MerkleTreeError::InvalidInstructionData.into())
+        );
+        assert!(tree.data.iter().all(|&byte| byte == 0));
+    }
+}
@@ -1,51 +1,230 @@
 use crate::{
     errors::MerkleTreeError,
-    state::MerkleTreeAccount,
-    utils::{build_merkle_root, hash_leaf},
+    instructions::{check_leaf_encoding, LeafInput},
+    state::{MerkleTreeAccount, TreeConfig, TreeStorage, CURRENT_ACCOUNT_VERSION},
+    utils::{build_merkle_root, tree_depth},
 };
 use solana_program::{
     account_info::{next_account_info, AccountInfo},
     entrypoint::ProgramResult,
+    hash::Hash,
     program_error::ProgramError,
-    program_pack::Pack,
+    program_pack::IsInitialized,
     pubkey::Pubkey,
 };
 
+/// Initializes a tree from raw leaf data, or from leaf hashes which are then
+/// stored as they are and flagged as pre-hashed.
 pub fn process_build_tree(
     program_id: &Pubkey,
     accounts: &[AccountInfo],
-    data: Vec<Vec<u8>>, // Each inner Vec<u8> is raw leaf data
+    config: TreeConfig,
+    leaves: Vec<LeafInput>,
+    root_history_size: u8,
 ) -> ProgramResult {
     let account_info_iter = &mut accounts.iter();
     let tree_account_info = next_account_info(account_info_iter)?;
+    let authority_info = next_account_info(account_info_iter)?;
 
-    // Ensure this account is owned by the program
-    if tree_account_info.owner != program_id {
-        return Err(ProgramError::IncorrectProgramId);
-    }
+    let mut tree_account = load_uninitialized(
+        program_id,
+        tree_account_info,
+        authority_info,
+        MerkleTreeAccount::unpack_unchecked,
+    )?;
 
-    // Unpack the existing MerkleTreeAccount
-    let mut tree_account = MerkleTreeAccount::unpack(&tree_account_info.try_borrow_data()?)?;
-
-    if tree_account.is_initialized {
-        return Err(MerkleTreeError::AccountAlreadyInitialized.into());
+    // All leaves must be sent the same way; the first one decides
+    tree_account.leaves_prehashed = leaves.first().is_some_and(LeafInput::is_prehashed);
+    for leaf in &leaves {
+        check_leaf_encoding(&tree_account, leaf)?;
     }
 
     // Convert each leaf to a Solana `Hash`
-    let hashed_leaves = data
-        .into_iter()
-        .map(|bytes| hash_leaf(&bytes))
+    let hashed_leaves = leaves
+        .iter()
+        .map(|leaf| leaf.to_hash(&config))
         .collect::<Vec<_>>();
 
     // Build the Merkle root from those leaves
-    let root = build_merkle_root(&hashed_leaves)?;
+    let root = build_merkle_root(&config, &hashed_leaves)?;
+
+    let leaf_count =
+        u32::try_from(hashed_leaves.len()).map_err(|_| MerkleTreeError::InvalidInstructionData)?;
 
     // Set fields and mark as initialized
     tree_account.is_initialized = true;
+    tree_account.version = CURRENT_ACCOUNT_VERSION;
+    tree_account.config = config;
+    tree_account.storage = TreeStorage::Leaves;
+    tree_account.leaf_count = leaf_count;
+    tree_account.depth = tree_depth(hashed_leaves.len());
+    tree_account.authority = *authority_info.key;
     tree_account.root = root;
+    tree_account.root_history_size = root_history_size;
+    tree_account.root_history = Vec::new();
     tree_account.leaves = hashed_leaves;
 
     // Pack the updated MerkleTreeAccount data back into the account
     MerkleTreeAccount::pack(tree_account, &mut tree_account_info.try_borrow_mut_data()?)?;
     Ok(())
 }
+
+/// Initializes a root-only tree from a root computed off-chain. No leaves are
+/// stored, so the account size does not depend on `leaf_count`.
+pub fn process_build_root_only_tree(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    config: TreeConfig,
+    leaves_prehashed: bool,
+    leaf_count: u32,
+    root: Hash,
+    root_history_size: u8,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let tree_account_info = next_account_info(account_info_iter)?;
+    let authority_info = next_account_info(account_info_iter)?;
+
+    let mut tree_account = load_uninitialized(
+        program_id,
+        tree_account_info,
+        authority_info,
+        MerkleTreeAccount::unpack_unchecked,
+    )?;
+
+    if leaf_count == 0 {
+        return Err(MerkleTreeError::EmptyTree.into());
+    }
+
+    // Set fields and mark as initialized
+    tree_account.is_initialized = true;
+    tree_account.version = CURRENT_ACCOUNT_VERSION;
+    tree_account.config = config;
+    tree_account.leaves_prehashed = leaves_prehashed;
+    tree_account.storage = TreeStorage::RootOnly;
+    tree_account.leaf_count = leaf_count;
+    tree_account.depth = tree_depth(leaf_count as usize);
+    tree_account.authority = *authority_info.key;
+    tree_account.root = root;
+    tree_account.root_history_size = root_history_size;
+    tree_account.root_history = Vec::new();
+    tree_account.leaves = Vec::new();
+
+    // Pack the updated MerkleTreeAccount data back into the account
+    MerkleTreeAccount::pack(tree_account, &mut tree_account_info.try_borrow_mut_data()?)?;
+    Ok(())
+}
+
+/// Checks that a new tree can be initialized in `tree_account_info` on behalf
+/// of `authority_info`, and loads the still empty account with
+/// `unpack_unchecked`, the unpacking function of its account type.
+pub(crate) fn load_uninitialized<T: IsInitialized>(
+    program_id: &Pubkey,
+    tree_account_info: &AccountInfo,
+    authority_info: &AccountInfo,
+    unpack_unchecked: fn(&[u8]) -> Result<T, ProgramError>,
+) -> Result<T, ProgramError> {
+    // Ensure this account is owned by the program
+    if tree_account_info.owner != program_id {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+
+    // The future authority has to agree to own the tree
+    if !authority_info.is_signer {
+        return Err(MerkleTreeError::Unauthorized.into());
+    }
+
+    // Unpack the existing account (not yet initialized)
+    let tree_account = unpack_unchecked(&tree_account_info.try_borrow_data()?)?;
+
+    if tree_account.is_initialized() {
+        return Err(MerkleTreeError::AccountAlreadyInitialized.into());
+    }
+
+    Ok(tree_account)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        instructions::test_accounts::TestAccount,
+        state::{HashFunction, NodeHashing},
+    };
+
+    const OZ_CONFIG: TreeConfig = TreeConfig {
+        hash_function: HashFunction::Keccak256,
+        node_hashing: NodeHashing::SortedPair,
+        domain_separated: false,
+    };
+
+    /// Roots of `SimpleMerkleTree.of(leaves, { sortLeaves: false })` from
+    /// `@openzeppelin/merkle-tree`, where leaf `i` is `keccak256("leaf {i}")`.
+    const OZ_ROOTS: [(usize, &str); 7] = [
+        (
+            1,
+            "5e1bfd352c3f7fb144d526cac5eb277d0611abe9c9c02ca1a621a5c192858c02",
+        ),
+        (
+            2,
+            "a5daec84ae0ff4b4e1337a0f364e50b899f62f6e03aa610f1395c88044691c02",
+        ),
+        (
+            3,
+            "ebb218fc1192604ee94985240dec1433c7786f9808ae8cb66750680ee52e1065",
+        ),
+        (
+            4,
+            "a0b3b6aa0f415da8d0c3067a794147d84c14c2b6371fb4f275f9f24c695eece7",
+        ),
+        (
+            5,
+            "27468f77d9677584fc7b8f29449957119cb531b1036f9f9ba09aa6725b0c55d7",
+        ),
+        (
+            6,
+            "4de9db9f28e757b1d0f9270c4a2037f8b45ddd86dd8d859c882322437fd28a0e",
+        ),
+        (
+            8,
+            "919b1e2975ee9409b55c8c20e402512c9ebcdb9889940cdd05aaf168002d2ede",
+        ),
+    ];
+
+    fn from_hex(hex: &str) -> Hash {
+        let bytes = (0..hex.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).unwrap())
+            .collect::<Vec<_>>();
+        Hash::new(&bytes)
+    }
+
+    fn build_tree_root(leaf_count: usize) -> Hash {
+        let program_id = Pubkey::new_unique();
+        let mut tree = TestAccount::new(program_id, MerkleTreeAccount::space_for(leaf_count, 0));
+        let mut authority = TestAccount::signer();
+        let leaves = (0..leaf_count)
+            .map(|i| LeafInput::Data(format!("leaf {i}").into_bytes()))
+            .collect();
+
+        process_build_tree(
+            &program_id,
+            &[tree.info(), authority.info()],
+            OZ_CONFIG,
+            leaves,
+            0,
+        )
+        .unwrap();
+        MerkleTreeAccount::unpack(&tree.data).unwrap().root
+    }
+
+    #[test]
+    fn keccak_sorted_pair_roots_match_openzeppelin() {
+        for (leaf_count, oz_root) in OZ_ROOTS {
+            assert_eq!(
+                build_tree_root(leaf_count),
+                from_hex(oz_root),
+                "{leaf_count} leaves"
+            );
+        }
+    }
+}
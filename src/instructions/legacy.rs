@@ -0,0 +1,182 @@
+//! Decoder for the original hand-rolled instruction format.
+//!
+//! Each instruction starts with its one-byte tag, followed by its fields:
+//! leaves are prefixed with a `u8` length and the leaf index is a
+//! little-endian `u32`. Only the three original instructions exist in this
+//! format; they act on trees with the default `TreeConfig` and no root
+//! history. Leaves longer than 255 bytes can only be sent in the Borsh format.
+//!
+//! The original `VerifyProof` carried no leaf index, so it has no
+//! `MerkleInstruction` equivalent and is decoded by `unpack_verify_proof`.
+
+use solana_program::{hash::Hash, msg, program_error::ProgramError};
+
+use crate::{instructions::MerkleInstruction, state::TreeConfig};
+
+/// Unpacks legacy instruction data into a `MerkleInstruction` enum.
+pub fn unpack(instruction_data: &[u8]) -> Result<MerkleInstruction, ProgramError> {
+    let (tag, rest) = instruction_data
+        .split_first()
+        .ok_or(ProgramError::InvalidInstructionData)?;
+
+    match tag {
+        0 => {
+            // BuildTree instruction
+            let data = unpack_leaf_data(rest)?;
+            Ok(MerkleInstruction::BuildTree {
+                config: TreeConfig::default(),
+                data,
+                root_history_size: 0,
+            })
+        }
+        1 => {
+            // BuildProof instruction; the original program always logged the proof
+            let leaf_index = u32::from_le_bytes(
+                rest.try_into()
+                    .map_err(|_| ProgramError::InvalidInstructionData)?,
+            );
+            Ok(MerkleInstruction::BuildProof {
+                leaf_index,
+                log_proof: true,
+            })
+        }
+        2 => {
+            // VerifyProof instruction, which carried no leaf index
+            msg!("VerifyProof without a leaf index has no MerkleInstruction equivalent.");
+            Err(ProgramError::InvalidInstructionData)
+        }
+        _ => Err(ProgramError::InvalidInstructionData),
+    }
+}
+
+/// The original `VerifyProof` instruction.
+#[derive(Clone, Debug, PartialEq)]
+pub struct UnindexedVerifyProof {
+    pub leaf_data: Vec<u8>,
+    pub proof: Vec<Hash>,
+}
+
+/// Unpacks a legacy `VerifyProof` instruction, or returns `None` for any
+/// other instruction.
+///
+/// The leaf has a `u8` length prefix and is followed by the 32-byte sibling
+/// hashes, up to the end of the instruction data.
+pub fn unpack_verify_proof(
+    instruction_data: &[u8],
+) -> Result<Option<UnindexedVerifyProof>, ProgramError> {
+    let Some((2, rest)) = instruction_data.split_first() else {
+        return Ok(None);
+    };
+
+    let (&leaf_length, rest) = rest
+        .split_first()
+        .ok_or(ProgramError::InvalidInstructionData)?;
+    if rest.len() < leaf_length as usize {
+        return Err(ProgramError::InvalidInstructionData);
+    }
+    let (leaf_data, proof) = rest.split_at(leaf_length as usize);
+    if proof.len() % 32 != 0 {
+        return Err(ProgramError::InvalidInstructionData);
+    }
+
+    Ok(Some(UnindexedVerifyProof {
+        leaf_data: leaf_data.to_vec(),
+        proof: proof.chunks(32).map(Hash::new).collect(),
+    }))
+}
+
+/// Splits a sequence of `u8`-length-prefixed leaves.
+fn unpack_leaf_data(mut rest: &[u8]) -> Result<Vec<Vec<u8>>, ProgramError> {
+    let mut data = Vec::new();
+    while let Some((length, tail)) = rest.split_first() {
+        let length = *length as usize;
+        if tail.len() < length {
+            return Err(ProgramError::InvalidInstructionData);
+        }
+        let (leaf, tail) = tail.split_at(length);
+        data.push(leaf.to_vec());
+        rest = tail;
+    }
+    Ok(data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decodes_the_original_build_tree_layout() {
+        assert_eq!(
+            unpack(&[0, 3, b'a', b'b', b'c', 0]),
+            Ok(MerkleInstruction::BuildTree {
+                config: TreeConfig::default(),
+                data: vec![b"abc".to_vec(), Vec::new()],
+                root_history_size: 0,
+            })
+        );
+        assert_eq!(
+            unpack(&[0, 3, b'a', b'b']),
+            Err(ProgramError::InvalidInstructionData)
+        );
+    }
+
+    #[test]
+    fn leaves_are_limited_to_255_bytes() {
+        let mut data = vec![0, 255];
+        data.extend([b'x'; 255]);
+        assert_eq!(
+            unpack(&data),
+            Ok(MerkleInstruction::BuildTree {
+                config: TreeConfig::default(),
+                data: vec![vec![b'x'; 255]],
+                root_history_size: 0,
+            })
+        );
+
+        // A `u8` prefix cannot announce a 256th byte, which is then read as
+        // the length of a truncated second leaf.
+        data.push(b'x');
+        assert_eq!(unpack(&data), Err(ProgramError::InvalidInstructionData));
+    }
+
+    #[test]
+    fn decodes_the_original_build_proof_layout() {
+        assert_eq!(
+            unpack(&[1, 7, 0, 0, 0]),
+            Ok(MerkleInstruction::BuildProof {
+                leaf_index: 7,
+                log_proof: true,
+            })
+        );
+        assert_eq!(
+            unpack(&[1, 7, 0, 0, 0, 1]),
+            Err(ProgramError::InvalidInstructionData)
+        );
+    }
+
+    #[test]
+    fn decodes_the_original_verify_proof_layout() {
+        let mut data = vec![2, 3, b'a', b'b', b'c'];
+        data.extend([1; 32]);
+        data.extend([2; 32]);
+        assert_eq!(
+            unpack_verify_proof(&data),
+            Ok(Some(UnindexedVerifyProof {
+                leaf_data: b"abc".to_vec(),
+                proof: vec![Hash::new_from_array([1; 32]), Hash::new_from_array([2; 32])],
+            }))
+        );
+        assert_eq!(unpack(&data), Err(ProgramError::InvalidInstructionData));
+
+        data.pop();
+        assert_eq!(
+            unpack_verify_proof(&data),
+            Err(ProgramError::InvalidInstructionData)
+        );
+        assert_eq!(
+            unpack_verify_proof(&[2, 3, b'a', b'b']),
+            Err(ProgramError::InvalidInstructionData)
+        );
+        assert_eq!(unpack_verify_proof(&[1, 7, 0, 0, 0]), Ok(None));
+    }
+}
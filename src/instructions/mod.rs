@@ -1,111 +1,607 @@
+pub mod append_concurrent_leaves;
+pub mod append_incremental_leaves;
+pub mod append_leaves;
+pub mod append_mmr_leaves;
+pub mod build_concurrent_tree;
+pub mod build_incremental_tree;
+pub mod build_mmr;
 pub mod build_proof;
+pub mod build_sparse_tree;
 pub mod build_tree;
+mod legacy;
+pub mod replace_concurrent_leaf;
+pub mod replace_leaf;
+pub mod set_authority;
+#[cfg(test)]
+mod test_accounts;
+pub mod update_sparse_leaf;
+mod v1;
+pub mod verify_incremental_proof;
+pub mod verify_mmr_proof;
+pub mod verify_multiproof;
 pub mod verify_proof;
+pub mod verify_sparse_proof;
 
+use borsh::{BorshDeserialize, BorshSerialize};
 use solana_program::{
     account_info::AccountInfo, entrypoint::ProgramResult, hash::Hash, program_error::ProgramError,
     pubkey::Pubkey,
 };
 
+use crate::errors::MerkleTreeError;
+use crate::utils::MerkleHasher;
+
+use crate::instructions::append_concurrent_leaves::process_append_concurrent_leaves;
+use crate::instructions::append_incremental_leaves::process_append_incremental_leaves;
+use crate::instructions::append_leaves::process_append_leaves;
+use crate::instructions::append_mmr_leaves::process_append_mmr_leaves;
+use crate::instructions::build_concurrent_tree::process_build_concurrent_tree;
+use crate::instructions::build_incremental_tree::process_build_incremental_tree;
+use crate::instructions::build_mmr::process_build_mmr;
 use crate::instructions::build_proof::process_build_proof;
-use crate::instructions::build_tree::process_build_tree;
-use crate::instructions::verify_proof::process_verify_proof;
+use crate::instructions::build_sparse_tree::process_build_sparse_tree;
+use crate::instructions::build_tree::{process_build_root_only_tree, process_build_tree};
+use crate::instructions::replace_concurrent_leaf::process_replace_concurrent_leaf;
+use crate::instructions::replace_leaf::process_replace_leaf;
+use crate::instructions::set_authority::process_set_authority;
+use crate::instructions::update_sparse_leaf::process_update_sparse_leaf;
+use crate::instructions::verify_incremental_proof::process_verify_incremental_proof;
+use crate::instructions::verify_mmr_proof::process_verify_mmr_proof;
+use crate::instructions::verify_multiproof::process_verify_multiproof;
+use crate::instructions::verify_proof::{process_verify_proof, process_verify_unindexed_proof};
+use crate::instructions::verify_sparse_proof::process_verify_sparse_proof;
+use crate::mmr::MmrProof;
+use crate::sparse::{SparseKey, SparseMerkleProof};
+use crate::state::{MerkleTreeAccount, TreeConfig};
+
+/// First byte of instruction data in the versioned Borsh format. Legacy
+/// instructions start with their tag instead, which is always smaller.
+pub const BORSH_INSTRUCTION_MARKER: u8 = 0xFF;
+
+/// Version of the Borsh instruction format, stored right after the marker.
+pub const INSTRUCTION_VERSION: u8 = 2;
 
 /// Instruction variants for the Merkle tree program.
+///
+/// Instruction data is `BORSH_INSTRUCTION_MARKER`, `INSTRUCTION_VERSION` and
+/// the Borsh encoding of this enum, where leaf data carries a `u32` length
+/// prefix. The original `BuildTree` and `BuildProof` instructions are still
+/// decoded from the legacy tagged format, whose `u8` length prefixes limit
+/// leaves to 255 bytes. The original `VerifyProof`, which had no leaf index,
+/// is processed directly by `process_instruction`.
+///
+/// Every instruction takes the tree account first. Mutating instructions also
+/// take the tree's authority as a signer in second position.
+#[derive(BorshSerialize, BorshDeserialize, Clone, Debug, PartialEq)]
 pub enum MerkleInstruction {
-    /// Initializes a Merkle tree with a list of leaf nodes.
-    BuildTree { data: Vec<Vec<u8>> },
+    /// Initializes a Merkle tree with a list of leaf nodes and the hashing
+    /// scheme every later instruction on this tree will use. The signing
+    /// second account becomes the tree's authority. The tree keeps its last
+    /// `root_history_size` previous roots, which `VerifyProof` also accepts.
+    BuildTree {
+        config: TreeConfig,
+        data: Vec<Vec<u8>>,
+        root_history_size: u8,
+    },
 
-    /// Generates a Merkle proof for a given leaf index.
-    BuildProof { leaf_index: u32 },
+    /// Generates a Merkle proof for a given leaf index and returns it as a
+    /// Borsh-serialized `MerkleProof` in the transaction's return data.
+    /// When `log_proof` is set the siblings are also written to the logs.
+    BuildProof { leaf_index: u32, log_proof: bool },
 
-    /// Verifies a Merkle proof for a given leaf.
+    /// Verifies a Merkle proof for the leaf at `leaf_index`. The tree account
+    /// is only read. The outcome is returned as a Borsh-serialized
+    /// `cpi::VerificationResult` in the return data; the instruction itself
+    /// only fails when the tree account cannot be read. Proofs against one of
+    /// the tree's historic roots are accepted too, and the result says which.
     VerifyProof {
+        leaf_index: u32,
         leaf_data: Vec<u8>,
         proof: Vec<Hash>,
     },
+
+    /// Hashes and appends leaf nodes to an initialized tree.
+    AppendLeaves { data: Vec<Vec<u8>> },
+
+    /// Replaces the leaf at `index`, which must currently hold `old_leaf`,
+    /// with the hash of `new_leaf_data`. `proof` is the proof of `old_leaf`
+    /// and is reused to update the root along the leaf's path.
+    ReplaceLeaf {
+        index: u32,
+        old_leaf: Hash,
+        new_leaf_data: Vec<u8>,
+        proof: Vec<Hash>,
+    },
+
+    /// Hands control of the tree over to `new_authority`.
+    SetAuthority { new_authority: Pubkey },
+
+    /// Like `BuildTree`, but stores the supplied leaf hashes as they are and
+    /// marks the tree as holding pre-hashed leaves.
+    BuildTreeFromHashes {
+        config: TreeConfig,
+        leaves: Vec<Hash>,
+        root_history_size: u8,
+    },
+
+    /// Like `AppendLeaves`, for trees of pre-hashed leaves.
+    AppendLeafHashes { leaves: Vec<Hash> },
+
+    /// Like `VerifyProof`, for trees of pre-hashed leaves.
+    VerifyProofForHash {
+        leaf_index: u32,
+        leaf: Hash,
+        proof: Vec<Hash>,
+    },
+
+    /// Like `ReplaceLeaf`, for trees of pre-hashed leaves.
+    ReplaceLeafHash {
+        index: u32,
+        old_leaf: Hash,
+        new_leaf: Hash,
+        proof: Vec<Hash>,
+    },
+
+    /// Initializes a root-only tree from a root and leaf count computed
+    /// off-chain, e.g. with `utils::build_merkle_root`. The account only needs
+    /// `MerkleTreeAccount::space_for(0, root_history_size)` bytes whatever the
+    /// number of leaves.
+    /// `BuildProof`, `AppendLeaves` and `ReplaceLeaf` are unavailable on such
+    /// trees, and proofs must be built off-chain from the full leaf set. Trees that need
+    /// on-chain appends without stored leaves should use `BuildIncrementalTree`.
+    BuildRootOnlyTree {
+        config: TreeConfig,
+        leaves_prehashed: bool,
+        leaf_count: u32,
+        root: Hash,
+        root_history_size: u8,
+    },
+
+    /// Verifies a multiproof for several leaves at once, e.g. one built by
+    /// `MerkleTree::multiproof`. `leaf_indices` must be strictly ascending and
+    /// `leaf_data` in the same order. The outcome is returned like for
+    /// `VerifyProof`.
+    VerifyMultiProof {
+        leaf_indices: Vec<u32>,
+        leaf_data: Vec<Vec<u8>>,
+        proof: Vec<Hash>,
+    },
+
+    /// Like `VerifyMultiProof`, for trees of pre-hashed leaves.
+    VerifyMultiProofForHashes {
+        leaf_indices: Vec<u32>,
+        leaves: Vec<Hash>,
+        proof: Vec<Hash>,
+    },
+
+    /// Initializes an empty sparse Merkle tree in an account of
+    /// `SparseMerkleTreeAccount::LEN` bytes. The signing second account becomes
+    /// the tree's authority. `config` must use positional node hashing.
+    BuildSparseTree { config: TreeConfig },
+
+    /// Stores `value` under `key`, which must be absent; `proof` is the proof
+    /// of `key`'s empty slot, e.g. from `SparseMerkleTree::proof`.
+    SparseInsert {
+        key: SparseKey,
+        value: Vec<u8>,
+        proof: SparseMerkleProof,
+    },
+
+    /// Removes `key`, which must currently hold `value`; `proof` is the proof
+    /// of that value.
+    SparseDelete {
+        key: SparseKey,
+        value: Vec<u8>,
+        proof: SparseMerkleProof,
+    },
+
+    /// Verifies that `key` holds `value`, or that `key` is absent when `value`
+    /// is `None`. The outcome is returned like for `VerifyProof`.
+    VerifySparseProof {
+        key: SparseKey,
+        value: Option<Vec<u8>>,
+        proof: SparseMerkleProof,
+    },
+
+    /// Initializes an empty concurrent Merkle tree of `max_depth` levels that
+    /// keeps its last `max_buffer_size` changes, in an account of
+    /// `ConcurrentMerkleTreeAccount::space_for(max_depth, max_buffer_size)`
+    /// bytes. The signing second account becomes the tree's authority.
+    BuildConcurrentTree {
+        config: TreeConfig,
+        max_depth: u8,
+        max_buffer_size: u32,
+    },
+
+    /// Hashes and appends leaf nodes to a concurrent tree. No proof is needed.
+    AppendConcurrentLeaves { data: Vec<Vec<u8>> },
+
+    /// Replaces the leaf at `index` of a concurrent tree, which must hold
+    /// `old_leaf`, with the hash of `new_leaf_data`. `proof` is the proof of
+    /// `old_leaf` against `root`, which may be any root still in the changelog
+    /// buffer.
+    ReplaceConcurrentLeaf {
+        root: Hash,
+        index: u32,
+        old_leaf: Hash,
+        new_leaf_data: Vec<u8>,
+        proof: Vec<Hash>,
+    },
+
+    /// Initializes an empty incremental Merkle tree of `depth` levels in an
+    /// account of `IncrementalMerkleTreeAccount::space_for(depth)` bytes. Leaves
+    /// are not stored, so proofs must be built off-chain with
+    /// `IncrementalMerkleTree`. The signing second account becomes the tree's
+    /// authority.
+    BuildIncrementalTree { config: TreeConfig, depth: u8 },
+
+    /// Hashes and appends leaf nodes to an incremental tree, failing once it
+    /// holds `2^depth` leaves. No proof is needed.
+    AppendIncrementalLeaves { data: Vec<Vec<u8>> },
+
+    /// Initializes an empty Merkle mountain range in an account of
+    /// `MerkleMountainRangeAccount::LEN` bytes. The signing second account
+    /// becomes its authority.
+    BuildMmr { config: TreeConfig },
+
+    /// Hashes and appends leaf nodes to a Merkle mountain range, updating its
+    /// peaks and bagged root. No proof is needed.
+    AppendMmrLeaves { data: Vec<Vec<u8>> },
+
+    /// Verifies that `leaf_data` is the leaf at `proof.leaf_index` of a Merkle
+    /// mountain range, against the current peaks, e.g. with a proof from
+    /// `MerkleMountainRange::proof` or one brought up to date with
+    /// `mmr::upgrade_mmr_proof`. The outcome is returned like for
+    /// `VerifyProof`.
+    VerifyMmrProof { leaf_data: Vec<u8>, proof: MmrProof },
+
+    /// Verifies that `leaf_data` is the leaf at `leaf_index` of an incremental
+    /// tree, against its current root, e.g. with a proof from
+    /// `IncrementalMerkleTree::proof`. The proof has one sibling per level,
+    /// empty subtrees included, and goes stale once more leaves are appended.
+    /// The outcome is returned like for `VerifyProof`.
+    VerifyIncrementalProof {
+        leaf_index: u32,
+        leaf_data: Vec<u8>,
+        proof: Vec<Hash>,
+    },
+}
+
+/// A leaf as sent by the caller.
+#[derive(Clone, Debug, PartialEq)]
+pub enum LeafInput {
+    /// Raw leaf data, hashed by the program with the tree's scheme.
+    Data(Vec<u8>),
+
+    /// A leaf hash computed off-chain, used as is.
+    Hash(Hash),
+}
+
+impl LeafInput {
+    /// Whether the leaf was supplied already hashed.
+    pub fn is_prehashed(&self) -> bool {
+        matches!(self, LeafInput::Hash(_))
+    }
+
+    /// Returns the leaf's hash under `config`.
+    pub fn to_hash(&self, config: &TreeConfig) -> Hash {
+        match self {
+            LeafInput::Data(data) => config.hash_leaf(data),
+            LeafInput::Hash(hash) => *hash,
+        }
+    }
 }
 
 impl MerkleInstruction {
-    /// Unpacks raw instruction data into a `MerkleInstruction` enum.
+    /// Packs a `MerkleInstruction` into the versioned Borsh format.
+    pub fn pack(&self) -> Result<Vec<u8>, ProgramError> {
+        let mut buf = vec![BORSH_INSTRUCTION_MARKER, INSTRUCTION_VERSION];
+        borsh::to_writer(&mut buf, self).map_err(|e| ProgramError::BorshIoError(e.to_string()))?;
+        Ok(buf)
+    }
+
+    /// Unpacks raw instruction data into a `MerkleInstruction` enum, accepting
+    /// the current and previous versions of the Borsh format as well as the
+    /// legacy tagged format.
     pub fn unpack(instruction_data: &[u8]) -> Result<Self, ProgramError> {
-        let (tag, rest) = instruction_data
-            .split_first()
-            .ok_or(ProgramError::InvalidInstructionData)?;
-
-        match tag {
-            0 => {
-                // BuildTree instruction
-                let mut data = Vec::new();
-                let mut offset = 0;
-                while offset < rest.len() {
-                    let length = rest[offset] as usize;
-                    offset += 1;
-                    if offset + length > rest.len() {
-                        return Err(ProgramError::InvalidInstructionData);
-                    }
-                    data.push(rest[offset..offset + length].to_vec());
-                    offset += length;
-                }
-                Ok(MerkleInstruction::BuildTree { data })
+        match instruction_data {
+            [BORSH_INSTRUCTION_MARKER, INSTRUCTION_VERSION, rest @ ..] => {
+                Self::try_from_slice(rest).map_err(|_| ProgramError::InvalidInstructionData)
             }
-            1 => {
-                // BuildProof instruction
-                if rest.len() != 4 {
-                    return Err(ProgramError::InvalidInstructionData);
-                }
-                let leaf_index = u32::from_le_bytes(
-                    rest.try_into()
-                        .map_err(|_| ProgramError::InvalidInstructionData)?,
-                );
-                Ok(MerkleInstruction::BuildProof { leaf_index })
-            }
-            2 => {
-                // VerifyProof instruction
-                let (leaf_length, rest) = rest
-                    .split_first()
-                    .ok_or(ProgramError::InvalidInstructionData)?;
-                let leaf_length = *leaf_length as usize;
-                if rest.len() < leaf_length {
-                    return Err(ProgramError::InvalidInstructionData);
-                }
-                let leaf_data = rest[..leaf_length].to_vec();
-
-                // Deserialize the proof into a vector of `Hash`
-                let mut proof = Vec::new();
-                let mut offset = leaf_length;
-                while offset < rest.len() {
-                    if rest[offset..].len() < 32 {
-                        return Err(ProgramError::InvalidInstructionData);
-                    }
-                    let hash_bytes: [u8; 32] = rest[offset..offset + 32]
-                        .try_into()
-                        .map_err(|_| ProgramError::InvalidInstructionData)?;
-                    proof.push(Hash::new(&hash_bytes));
-                    offset += 32;
-                }
-
-                Ok(MerkleInstruction::VerifyProof { leaf_data, proof })
-            }
-            _ => Err(ProgramError::InvalidInstructionData),
+            [BORSH_INSTRUCTION_MARKER, 1, rest @ ..] => v1::unpack(rest),
+            [BORSH_INSTRUCTION_MARKER, ..] => Err(ProgramError::InvalidInstructionData),
+            _ => legacy::unpack(instruction_data),
         }
     }
 }
 
+/// Ensures `leaf` is encoded the way the tree stores its leaves.
+fn check_leaf_encoding(tree_account: &MerkleTreeAccount, leaf: &LeafInput) -> ProgramResult {
+    if leaf.is_prehashed() != tree_account.leaves_prehashed {
+        return Err(MerkleTreeError::LeafEncodingMismatch.into());
+    }
+    Ok(())
+}
+
+/// Ensures `authority_info` is the tree's `authority` and signed the transaction.
+fn check_authority(authority: &Pubkey, authority_info: &AccountInfo) -> ProgramResult {
+    if !authority_info.is_signer || authority_info.key != authority {
+        return Err(MerkleTreeError::Unauthorized.into());
+    }
+    Ok(())
+}
+
 pub fn process_instruction(
     program_id: &Pubkey,
     accounts: &[AccountInfo],
     instruction_data: &[u8],
 ) -> ProgramResult {
+    if let Some(legacy::UnindexedVerifyProof { leaf_data, proof }) =
+        legacy::unpack_verify_proof(instruction_data)?
+    {
+        return process_verify_unindexed_proof(program_id, accounts, leaf_data, proof);
+    }
     let instruction = MerkleInstruction::unpack(instruction_data)?;
 
     match instruction {
-        MerkleInstruction::BuildTree { data } => process_build_tree(program_id, accounts, data),
-        MerkleInstruction::BuildProof { leaf_index } => {
-            process_build_proof(program_id, accounts, leaf_index)
+        MerkleInstruction::BuildTree {
+            config,
+            data,
+            root_history_size,
+        } => {
+            let leaves = data.into_iter().map(LeafInput::Data).collect();
+            process_build_tree(program_id, accounts, config, leaves, root_history_size)
+        }
+        MerkleInstruction::BuildProof {
+            leaf_index,
+            log_proof,
+        } => process_build_proof(program_id, accounts, leaf_index, log_proof),
+        MerkleInstruction::VerifyProof {
+            leaf_index,
+            leaf_data,
+            proof,
+        } => process_verify_proof(
+            program_id,
+            accounts,
+            leaf_index,
+            LeafInput::Data(leaf_data),
+            proof,
+        ),
+        MerkleInstruction::AppendLeaves { data } => {
+            let leaves = data.into_iter().map(LeafInput::Data).collect();
+            process_append_leaves(program_id, accounts, leaves)
+        }
+        MerkleInstruction::ReplaceLeaf {
+            index,
+            old_leaf,
+            new_leaf_data,
+            proof,
+        } => process_replace_leaf(
+            program_id,
+            accounts,
+            index,
+            old_leaf,
+            LeafInput::Data(new_leaf_data),
+            proof,
+        ),
+        MerkleInstruction::SetAuthority { new_authority } => {
+            process_set_authority(program_id, accounts, new_authority)
+        }
+        MerkleInstruction::BuildTreeFromHashes {
+            config,
+            leaves,
+            root_history_size,
+        } => {
+            let leaves = leaves.into_iter().map(LeafInput::Hash).collect();
+            process_build_tree(program_id, accounts, config, leaves, root_history_size)
+        }
+        MerkleInstruction::AppendLeafHashes { leaves } => {
+            let leaves = leaves.into_iter().map(LeafInput::Hash).collect();
+            process_append_leaves(program_id, accounts, leaves)
+        }
+        MerkleInstruction::VerifyProofForHash {
+            leaf_index,
+            leaf,
+            proof,
+        } => process_verify_proof(
+            program_id,
+            accounts,
+            leaf_index,
+            LeafInput::Hash(leaf),
+            proof,
+        ),
+        MerkleInstruction::ReplaceLeafHash {
+            index,
+            old_leaf,
+            new_leaf,
+            proof,
+        } => process_replace_leaf(
+            program_id,
+            accounts,
+            index,
+            old_leaf,
+            LeafInput::Hash(new_leaf),
+            proof,
+        ),
+        MerkleInstruction::BuildRootOnlyTree {
+            config,
+            leaves_prehashed,
+            leaf_count,
+            root,
+            root_history_size,
+        } => process_build_root_only_tree(
+            program_id,
+            accounts,
+            config,
+            leaves_prehashed,
+            leaf_count,
+            root,
+            root_history_size,
+        ),
+        MerkleInstruction::VerifyMultiProof {
+            leaf_indices,
+            leaf_data,
+            proof,
+        } => {
+            let leaves = leaf_data.into_iter().map(LeafInput::Data).collect();
+            process_verify_multiproof(program_id, accounts, leaf_indices, leaves, proof)
+        }
+        MerkleInstruction::VerifyMultiProofForHashes {
+            leaf_indices,
+            leaves,
+            proof,
+        } => {
+            let leaves = leaves.into_iter().map(LeafInput::Hash).collect();
+            process_verify_multiproof(program_id, accounts, leaf_indices, leaves, proof)
         }
-        MerkleInstruction::VerifyProof { leaf_data, proof } => {
-            process_verify_proof(program_id, accounts, leaf_data, proof)
+        MerkleInstruction::BuildSparseTree { config } => {
+            process_build_sparse_tree(program_id, accounts, config)
         }
+        MerkleInstruction::SparseInsert { key, value, proof } => {
+            process_update_sparse_leaf(program_id, accounts, key, None, Some(value), proof)
+        }
+        MerkleInstruction::SparseDelete { key, value, proof } => {
+            process_update_sparse_leaf(program_id, accounts, key, Some(value), None, proof)
+        }
+        MerkleInstruction::VerifySparseProof { key, value, proof } => {
+            process_verify_sparse_proof(program_id, accounts, key, value, proof)
+        }
+        MerkleInstruction::BuildConcurrentTree {
+            config,
+            max_depth,
+            max_buffer_size,
+        } => {
+            process_build_concurrent_tree(program_id, accounts, config, max_depth, max_buffer_size)
+        }
+        MerkleInstruction::AppendConcurrentLeaves { data } => {
+            process_append_concurrent_leaves(program_id, accounts, data)
+        }
+        MerkleInstruction::ReplaceConcurrentLeaf {
+            root,
+            index,
+            old_leaf,
+            new_leaf_data,
+            proof,
+        } => process_replace_concurrent_leaf(
+            program_id,
+            accounts,
+            root,
+            index,
+            old_leaf,
+            new_leaf_data,
+            proof,
+        ),
+        MerkleInstruction::BuildIncrementalTree { config, depth } => {
+            process_build_incremental_tree(program_id, accounts, config, depth)
+        }
+        MerkleInstruction::AppendIncrementalLeaves { data } => {
+            process_append_incremental_leaves(program_id, accounts, data)
+        }
+        MerkleInstruction::BuildMmr { config } => process_build_mmr(program_id, accounts, config),
+        MerkleInstruction::AppendMmrLeaves { data } => {
+            process_append_mmr_leaves(program_id, accounts, data)
+        }
+        MerkleInstruction::VerifyMmrProof { leaf_data, proof } => {
+            process_verify_mmr_proof(program_id, accounts, leaf_data, proof)
+        }
+        MerkleInstruction::VerifyIncrementalProof {
+            leaf_index,
+            leaf_data,
+            proof,
+        } => process_verify_incremental_proof(program_id, accounts, leaf_index, leaf_data, proof),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn round_trip(instruction: MerkleInstruction) {
+        let data = instruction.pack().unwrap();
+        assert_eq!(MerkleInstruction::unpack(&data), Ok(instruction));
+    }
+
+    #[test]
+    fn leaves_longer_than_255_bytes_round_trip() {
+        for len in [255, 256] {
+            let leaf = vec![0x5A; len];
+            round_trip(MerkleInstruction::BuildTree {
+                config: TreeConfig::default(),
+                data: vec![leaf.clone(), leaf.clone()],
+                root_history_size: 0,
+            });
+            round_trip(MerkleInstruction::VerifyProof {
+                leaf_index: 1,
+                leaf_data: leaf.clone(),
+                proof: vec![Hash::new_unique()],
+            });
+            round_trip(MerkleInstruction::ReplaceLeaf {
+                index: 1,
+                old_leaf: Hash::new_unique(),
+                new_leaf_data: leaf,
+                proof: vec![Hash::new_unique()],
+            });
+        }
+    }
+
+    /// Encodes `fields` as variant `tag` in version 1 of the Borsh format.
+    fn v1_data(tag: u8, fields: impl BorshSerialize) -> Vec<u8> {
+        let mut data = vec![BORSH_INSTRUCTION_MARKER, 1, tag];
+        borsh::to_writer(&mut data, &fields).unwrap();
+        data
+    }
+
+    #[test]
+    fn version_1_build_instructions_keep_no_root_history() {
+        let config = TreeConfig::default();
+        let (leaf, root) = (Hash::new_unique(), Hash::new_unique());
+
+        assert_eq!(
+            MerkleInstruction::unpack(&v1_data(0, (config, vec![b"a".to_vec()]))),
+            Ok(MerkleInstruction::BuildTree {
+                config,
+                data: vec![b"a".to_vec()],
+                root_history_size: 0,
+            })
+        );
+        assert_eq!(
+            MerkleInstruction::unpack(&v1_data(6, (config, vec![leaf]))),
+            Ok(MerkleInstruction::BuildTreeFromHashes {
+                config,
+                leaves: vec![leaf],
+                root_history_size: 0,
+            })
+        );
+        assert_eq!(
+            MerkleInstruction::unpack(&v1_data(10, (config, true, 3u32, root))),
+            Ok(MerkleInstruction::BuildRootOnlyTree {
+                config,
+                leaves_prehashed: true,
+                leaf_count: 3,
+                root,
+                root_history_size: 0,
+            })
+        );
+
+        // The version 2 encoding carries one more byte.
+        assert_eq!(
+            MerkleInstruction::unpack(&v1_data(0, (config, vec![b"a".to_vec()], 4u8))),
+            Err(ProgramError::InvalidInstructionData)
+        );
+    }
+
+    #[test]
+    fn version_1_decodes_unchanged_variants_as_they_are() {
+        assert_eq!(
+            MerkleInstruction::unpack(&v1_data(1, (7u32, false))),
+            Ok(MerkleInstruction::BuildProof {
+                leaf_index: 7,
+                log_proof: false,
+            })
+        );
+        assert_eq!(
+            MerkleInstruction::unpack(&[BORSH_INSTRUCTION_MARKER, 3, 1, 7, 0, 0, 0, 0]),
+            Err(ProgramError::InvalidInstructionData)
+        );
     }
 }
@@ -0,0 +1,236 @@
+use solana_program::{
+    account_info::{next_account_info, AccountInfo},
+    entrypoint::ProgramResult,
+    hash::Hash,
+    program_error::ProgramError,
+    pubkey::Pubkey,
+};
+
+use crate::{
+    instructions::check_authority, state::ConcurrentMerkleTreeAccount, utils::MerkleHasher,
+};
+
+/// Replaces a leaf of a concurrent tree using a proof against any root still
+/// in its changelog buffer.
+pub fn process_replace_concurrent_leaf(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    root: Hash,
+    index: u32,
+    old_leaf: Hash,
+    new_leaf_data: Vec<u8>,
+    proof: Vec<Hash>,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let tree_account_info = next_account_info(account_info_iter)?;
+    let authority_info = next_account_info(account_info_iter)?;
+
+    // Ensure the account is owned by the program.
+    if tree_account_info.owner != program_id {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+
+    // Load the concurrent tree account.
+    let mut data = tree_account_info.try_borrow_mut_data()?;
+    let mut tree_account = ConcurrentMerkleTreeAccount::unpack(&data)?;
+    check_authority(&tree_account.authority, authority_info)?;
+
+    // Fast-forward the proof, check it and apply the new leaf.
+    let config = tree_account.config;
+    let new_leaf = config.hash_leaf(&new_leaf_data);
+    tree_account.replace_leaf(&config, &mut data, root, index, old_leaf, new_leaf, &proof)?;
+
+    // Pack the updated ConcurrentMerkleTreeAccount header back into the account.
+    ConcurrentMerkleTreeAccount::pack(tree_account, &mut data)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        errors::MerkleTreeError,
+        instructions::{
+            append_concurrent_leaves::process_append_concurrent_leaves,
+            build_concurrent_tree::process_build_concurrent_tree, test_accounts::TestAccount,
+        },
+        merkle_tree::MerkleTree,
+        state::TreeConfig,
+    };
+
+    const DEPTH: u8 = 3;
+    const BUFFER_SIZE: u32 = 4;
+
+    /// A concurrent tree holding `leaves`, with its authority.
+    fn build(program_id: &Pubkey, leaves: &[&[u8]]) -> [TestAccount; 2] {
+        let mut tree = TestAccount::new(
+            *program_id,
+            ConcurrentMerkleTreeAccount::space_for(DEPTH, BUFFER_SIZE),
+        );
+        let mut authority = TestAccount::signer();
+        process_build_concurrent_tree(
+            program_id,
+            &[tree.info(), authority.info()],
+            TreeConfig::default(),
+            DEPTH,
+            BUFFER_SIZE,
+        )
+        .unwrap();
+        let data = leaves.iter().map(|leaf| leaf.to_vec()).collect();
+        process_append_concurrent_leaves(program_id, &[tree.info(), authority.info()], data)
+            .unwrap();
+        [tree, authority]
+    }
+
+    /// The same tree rebuilt off-chain, padded with empty leaves.
+    fn off_chain(leaves: &[&[u8]]) -> MerkleTree<TreeConfig> {
+        let config = TreeConfig::default();
+        let mut leaves = leaves
+            .iter()
+            .map(|leaf| config.hash_leaf(leaf))
+            .collect::<Vec<_>>();
+        leaves.resize(1 << DEPTH, config.empty_node());
+        MerkleTree::from_leaf_hashes(config, leaves).unwrap()
+    }
+
+    fn root(tree: &TestAccount) -> Hash {
+        ConcurrentMerkleTreeAccount::unpack(&tree.data)
+            .unwrap()
+            .root
+    }
+
+    /// Replaces `leaves[index]` with `new_leaf`, proving it against `snapshot`.
+    fn replace(
+        program_id: &Pubkey,
+        [tree, authority]: &mut [TestAccount; 2],
+        snapshot: &MerkleTree<TreeConfig>,
+        index: u32,
+        new_leaf: &[u8],
+    ) -> ProgramResult {
+        let proof = snapshot.proof(index).unwrap();
+        process_replace_concurrent_leaf(
+            program_id,
+            &[tree.info(), authority.info()],
+            snapshot.root(),
+            index,
+            proof.leaf,
+            new_leaf.to_vec(),
+            proof.siblings,
+        )
+    }
+
+    #[test]
+    fn proofs_apply_while_their_root_is_in_the_buffer() {
+        let program_id = Pubkey::new_unique();
+        let mut accounts = build(&program_id, &[b"a", b"b", b"c"]);
+        let snapshot = off_chain(&[b"a", b"b", b"c"]);
+        assert_eq!(root(&accounts[0]), snapshot.root());
+
+        // The buffer holds the snapshot's root and the changes made since, so
+        // a proof survives up to `BUFFER_SIZE - 1` replaces and appends.
+        replace(&program_id, &mut accounts, &snapshot, 1, b"x").unwrap();
+        let [tree, authority] = &mut accounts;
+        process_append_concurrent_leaves(
+            &program_id,
+            &[tree.info(), authority.info()],
+            vec![b"d".to_vec()],
+        )
+        .unwrap();
+        let current = off_chain(&[b"a", b"x", b"c", b"d"]);
+        assert_eq!(root(&accounts[0]), current.root());
+        replace(&program_id, &mut accounts, &current, 2, b"y").unwrap();
+
+        replace(&program_id, &mut accounts, &snapshot, 0, b"z").unwrap();
+        assert_eq!(
+            root(&accounts[0]),
+            off_chain(&[b"z", b"x", b"y", b"d"]).root()
+        );
+
+        // The last leaf's proof was kept in step, so appending still works.
+        let [tree, authority] = &mut accounts;
+        process_append_concurrent_leaves(
+            &program_id,
+            &[tree.info(), authority.info()],
+            vec![b"e".to_vec()],
+        )
+        .unwrap();
+        assert_eq!(
+            root(&accounts[0]),
+            off_chain(&[b"z", b"x", b"y", b"d", b"e"]).root()
+        );
+    }
+
+    #[test]
+    fn rejects_roots_evicted_from_the_buffer() {
+        let program_id = Pubkey::new_unique();
+        let mut accounts = build(&program_id, &[b"a"]);
+        let snapshot = off_chain(&[b"a"]);
+
+        let [tree, authority] = &mut accounts;
+        let later_leaves = (0..BUFFER_SIZE).map(|i| vec![i as u8]).collect();
+        process_append_concurrent_leaves(
+            &program_id,
+            &[tree.info(), authority.info()],
+            later_leaves,
+        )
+        .unwrap();
+        assert_eq!(
+            replace(&program_id, &mut accounts, &snapshot, 0, b"z"),
+            Err(MerkleTreeError::StaleRoot.into())
+        );
+    }
+
+    #[test]
+    fn rejects_proofs_of_a_leaf_changed_since() {
+        let program_id = Pubkey::new_unique();
+        let mut accounts = build(&program_id, &[b"a", b"b"]);
+        let snapshot = off_chain(&[b"a", b"b"]);
+
+        replace(&program_id, &mut accounts, &snapshot, 0, b"x").unwrap();
+        assert_eq!(
+            replace(&program_id, &mut accounts, &snapshot, 0, b"y"),
+            Err(MerkleTreeError::LeafMismatch.into())
+        );
+        assert_eq!(root(&accounts[0]), off_chain(&[b"x", b"b"]).root());
+    }
+
+    #[test]
+    fn rejects_a_wrong_proof() {
+        let program_id = Pubkey::new_unique();
+        let [mut tree, mut authority] = build(&program_id, &[b"a", b"b", b"c"]);
+        let snapshot = off_chain(&[b"a", b"b", b"c"]);
+        let proof = snapshot.proof(1).unwrap();
+        let config = TreeConfig::default();
+
+        let mut replace = |index, old_leaf, proof| {
+            process_replace_concurrent_leaf(
+                &program_id,
+                &[tree.info(), authority.info()],
+                snapshot.root(),
+                index,
+                old_leaf,
+                b"x".to_vec(),
+                proof,
+            )
+        };
+        let mut tampered = proof.siblings.clone();
+        tampered[1] = Hash::new_unique();
+        assert_eq!(
+            replace(1, proof.leaf, tampered),
+            Err(MerkleTreeError::InvalidProof.into())
+        );
+        assert_eq!(
+            replace(1, config.hash_leaf(b"c"), proof.siblings.clone()),
+            Err(MerkleTreeError::InvalidProof.into())
+        );
+        assert_eq!(
+            replace(1, proof.leaf, proof.siblings[1..].to_vec()),
+            Err(MerkleTreeError::InvalidProof.into())
+        );
+        assert_eq!(
+            replace(3, config.empty_node(), snapshot.proof(3).unwrap().siblings),
+            Err(MerkleTreeError::InvalidLeafIndex.into())
+        );
+        assert_eq!(root(&tree), snapshot.root());
+    }
+}
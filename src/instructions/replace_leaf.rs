@@ -0,0 +1,250 @@
+use solana_program::{
+    account_info::{next_account_info, AccountInfo},
+    entrypoint::ProgramResult,
+    hash::Hash,
+    program_error::ProgramError,
+    pubkey::Pubkey,
+};
+
+use crate::{
+    errors::MerkleTreeError,
+    instructions::{check_authority, check_leaf_encoding, LeafInput},
+    state::{MerkleTreeAccount, TreeStorage},
+    utils::recompute_root_with_leaf_hash,
+};
+
+/// Replaces a single stored leaf and updates the root along its path.
+pub fn process_replace_leaf(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    index: u32,
+    old_leaf: Hash,
+    new_leaf: LeafInput,
+    proof: Vec<Hash>,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let tree_account_info = next_account_info(account_info_iter)?;
+    let authority_info = next_account_info(account_info_iter)?;
+
+    // Ensure the account is owned by the program.
+    if tree_account_info.owner != program_id {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+
+    // Load the Merkle tree account.
+    let mut tree_account = MerkleTreeAccount::unpack(&tree_account_info.try_borrow_data()?)?;
+    check_authority(&tree_account.authority, authority_info)?;
+    check_leaf_encoding(&tree_account, &new_leaf)?;
+    let config = tree_account.config;
+
+    // Root-only trees have no leaves to replace.
+    if tree_account.storage == TreeStorage::RootOnly {
+        return Err(MerkleTreeError::LeavesNotStored.into());
+    }
+
+    // The caller must know what is currently stored at `index`.
+    let stored_leaf = tree_account
+        .leaves
+        .get(index as usize)
+        .ok_or(MerkleTreeError::InvalidLeafIndex)?;
+    if *stored_leaf != old_leaf {
+        return Err(MerkleTreeError::LeafMismatch.into());
+    }
+
+    // The proof must lead from the old leaf to the current root.
+    let old_root =
+        recompute_root_with_leaf_hash(&config, old_leaf, index, tree_account.leaf_count, &proof)?;
+    if old_root != tree_account.root {
+        return Err(MerkleTreeError::InvalidProof.into());
+    }
+
+    // Replay the same path with the new leaf to get the new root.
+    let new_leaf = new_leaf.to_hash(&config);
+    tree_account.leaves[index as usize] = new_leaf;
+    let leaf_count = tree_account.leaf_count;
+    let new_root = recompute_root_with_leaf_hash(&config, new_leaf, index, leaf_count, &proof)?;
+    tree_account.set_root(new_root, leaf_count);
+
+    // Pack the updated MerkleTreeAccount data back into the account.
+    MerkleTreeAccount::pack(tree_account, &mut tree_account_info.try_borrow_mut_data()?)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        instructions::{
+            build_tree::{process_build_root_only_tree, process_build_tree},
+            test_accounts::TestAccount,
+        },
+        merkle_tree::MerkleTree,
+        state::{HashFunction, NodeHashing, TreeConfig},
+        utils::MerkleHasher,
+    };
+
+    fn leaf_data(count: usize) -> Vec<Vec<u8>> {
+        (0..count)
+            .map(|i| format!("leaf {i}").into_bytes())
+            .collect()
+    }
+
+    /// Builds a tree storing `data` in a new account, returning the account
+    /// and its authority.
+    fn build(program_id: &Pubkey, config: TreeConfig, data: &[Vec<u8>]) -> [TestAccount; 2] {
+        let mut tree = TestAccount::new(*program_id, MerkleTreeAccount::space_for(data.len(), 0));
+        let mut authority = TestAccount::signer();
+        let leaves = data.iter().cloned().map(LeafInput::Data).collect();
+        process_build_tree(
+            program_id,
+            &[tree.info(), authority.info()],
+            config,
+            leaves,
+            0,
+        )
+        .unwrap();
+        [tree, authority]
+    }
+
+    #[test]
+    fn the_root_matches_an_off_chain_rebuild() {
+        let configs = [
+            TreeConfig::default(),
+            TreeConfig {
+                hash_function: HashFunction::Keccak256,
+                node_hashing: NodeHashing::SortedPair,
+                domain_separated: false,
+            },
+        ];
+        for config in configs {
+            let program_id = Pubkey::new_unique();
+            let mut data = leaf_data(5);
+            let [mut tree, mut authority] = build(&program_id, config, &data);
+
+            for index in 0..data.len() {
+                let proof = MerkleTree::new(config, &data)
+                    .unwrap()
+                    .proof(index as u32)
+                    .unwrap();
+                data[index] = format!("new leaf {index}").into_bytes();
+                process_replace_leaf(
+                    &program_id,
+                    &[tree.info(), authority.info()],
+                    proof.leaf_index,
+                    proof.leaf,
+                    LeafInput::Data(data[index].clone()),
+                    proof.siblings,
+                )
+                .unwrap();
+
+                let rebuilt = MerkleTree::new(config, &data).unwrap();
+                let tree_account = MerkleTreeAccount::unpack(&tree.data).unwrap();
+                assert_eq!(
+                    tree_account.root,
+                    rebuilt.root(),
+                    "{config:?}, index {index}"
+                );
+                assert_eq!(tree_account.leaves, rebuilt.leaves());
+            }
+        }
+    }
+
+    #[test]
+    fn rejects_a_wrong_old_leaf_or_a_tampered_proof() {
+        let program_id = Pubkey::new_unique();
+        let config = TreeConfig::default();
+        let data = leaf_data(5);
+        let off_chain = MerkleTree::new(config, &data).unwrap();
+        let proof = off_chain.proof(2).unwrap();
+        let wrong_leaf = config.hash_leaf(b"leaf 3");
+        let mut tampered = proof.siblings.clone();
+        tampered[1] = config.hash_leaf(b"tampered");
+
+        let [mut tree, mut authority] = build(&program_id, config, &data);
+        let before = tree.data.clone();
+
+        let mut replace = |old_leaf, proof| {
+            process_replace_leaf(
+                &program_id,
+                &[tree.info(), authority.info()],
+                2,
+                old_leaf,
+                LeafInput::Data(b"new leaf".to_vec()),
+                proof,
+            )
+        };
+        assert_eq!(
+            replace(wrong_leaf, proof.siblings.clone()),
+            Err(MerkleTreeError::LeafMismatch.into())
+        );
+        assert_eq!(
+            replace(proof.leaf, tampered),
+            Err(MerkleTreeError::InvalidProof.into())
+        );
+        assert_eq!(tree.data, before);
+    }
+
+    #[test]
+    fn root_only_trees_have_no_leaves_to_replace() {
+        let program_id = Pubkey::new_unique();
+        let config = TreeConfig::default();
+        let off_chain = MerkleTree::new(config, &leaf_data(2)).unwrap();
+        let proof = off_chain.proof(0).unwrap();
+        let mut tree = TestAccount::new(program_id, MerkleTreeAccount::space_for(0, 0));
+        let mut authority = TestAccount::signer();
+        process_build_root_only_tree(
+            &program_id,
+            &[tree.info(), authority.info()],
+            config,
+            false,
+            off_chain.leaf_count(),
+            off_chain.root(),
+            0,
+        )
+        .unwrap();
+
+        assert_eq!(
+            process_replace_leaf(
+                &program_id,
+                &[tree.info(), authority.info()],
+                0,
+                proof.leaf,
+                LeafInput::Data(b"new leaf".to_vec()),
+                proof.siblings,
+            ),
+            Err(MerkleTreeError::LeavesNotStored.into())
+        );
+    }
+
+    #[test]
+    fn only_the_signing_authority_may_replace() {
+        let program_id = Pubkey::new_unique();
+        let config = TreeConfig::default();
+        let data = leaf_data(2);
+        let proof = MerkleTree::new(config, &data).unwrap().proof(0).unwrap();
+        let [mut tree, mut authority] = build(&program_id, config, &data);
+        let mut intruder = TestAccount::signer();
+        let before = tree.data.clone();
+
+        let mut replace = |authority: &mut TestAccount| {
+            process_replace_leaf(
+                &program_id,
+                &[tree.info(), authority.info()],
+                0,
+                proof.leaf,
+                LeafInput::Data(b"new leaf".to_vec()),
+                proof.siblings.clone(),
+            )
+        };
+        assert_eq!(
+            replace(&mut intruder),
+            Err(MerkleTreeError::Unauthorized.into())
+        );
+        authority.is_signer = false;
+        assert_eq!(
+            replace(&mut authority),
+            Err(MerkleTreeError::Unauthorized.into())
+        );
+        assert_eq!(tree.data, before);
+    }
+}
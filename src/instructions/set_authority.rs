@@ -0,0 +1,165 @@
+use solana_program::{
+    account_info::{next_account_info, AccountInfo},
+    entrypoint::ProgramResult,
+    program_error::ProgramError,
+    pubkey::Pubkey,
+};
+
+use crate::{
+    instructions::check_authority,
+    state::{
+        AccountKind, ConcurrentMerkleTreeAccount, IncrementalMerkleTreeAccount,
+        MerkleMountainRangeAccount, MerkleTreeAccount, SparseMerkleTreeAccount,
+    },
+};
+
+/// Transfers control of a tree of any kind to `new_authority`.
+pub fn process_set_authority(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    new_authority: Pubkey,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let tree_account_info = next_account_info(account_info_iter)?;
+    let authority_info = next_account_info(account_info_iter)?;
+
+    // Ensure the account is owned by the program.
+    if tree_account_info.owner != program_id {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+
+    // Only the current authority may hand the tree over, whatever its kind.
+    let mut data = tree_account_info.try_borrow_mut_data()?;
+    let kind = AccountKind::try_from(*data.first().ok_or(ProgramError::InvalidAccountData)?)?;
+    match kind {
+        AccountKind::Uninitialized => Err(ProgramError::UninitializedAccount),
+        AccountKind::MerkleTree => {
+            let mut tree_account = MerkleTreeAccount::unpack(&data)?;
+            check_authority(&tree_account.authority, authority_info)?;
+            tree_account.authority = new_authority;
+            MerkleTreeAccount::pack(tree_account, &mut data)
+        }
+        AccountKind::SparseMerkleTree => {
+            let mut tree_account = SparseMerkleTreeAccount::unpack(&data)?;
+            check_authority(&tree_account.authority, authority_info)?;
+            tree_account.authority = new_authority;
+            SparseMerkleTreeAccount::pack(tree_account, &mut data)
+        }
+        AccountKind::ConcurrentMerkleTree => {
+            let mut tree_account = ConcurrentMerkleTreeAccount::unpack(&data)?;
+            check_authority(&tree_account.authority, authority_info)?;
+            tree_account.authority = new_authority;
+            ConcurrentMerkleTreeAccount::pack(tree_account, &mut data)
+        }
+        AccountKind::IncrementalMerkleTree => {
+            let mut tree_account = IncrementalMerkleTreeAccount::unpack(&data)?;
+            check_authority(&tree_account.authority, authority_info)?;
+            tree_account.authority = new_authority;
+            IncrementalMerkleTreeAccount::pack(tree_account, &mut data)
+        }
+        AccountKind::MerkleMountainRange => {
+            let mut mmr_account = MerkleMountainRangeAccount::unpack(&data)?;
+            check_authority(&mmr_account.authority, authority_info)?;
+            mmr_account.authority = new_authority;
+            MerkleMountainRangeAccount::pack(mmr_account, &mut data)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        errors::MerkleTreeError,
+        instructions::{
+            build_concurrent_tree::process_build_concurrent_tree,
+            build_incremental_tree::process_build_incremental_tree, build_mmr::process_build_mmr,
+            build_sparse_tree::process_build_sparse_tree, build_tree::process_build_tree,
+            test_accounts::TestAccount, LeafInput,
+        },
+        state::TreeConfig,
+    };
+
+    /// Builds an account of `space` bytes with `build`, then hands it over to a
+    /// new authority, checking the stored authority with `authority_of`.
+    fn hands_over(
+        space: usize,
+        build: impl Fn(&Pubkey, &[AccountInfo]) -> ProgramResult,
+        authority_of: impl Fn(&[u8]) -> Pubkey,
+    ) {
+        let program_id = Pubkey::new_unique();
+        let mut tree = TestAccount::new(program_id, space);
+        let mut authority = TestAccount::signer();
+        let mut intruder = TestAccount::signer();
+        let new_authority = Pubkey::new_unique();
+        build(&program_id, &[tree.info(), authority.info()]).unwrap();
+
+        assert_eq!(
+            process_set_authority(&program_id, &[tree.info(), intruder.info()], new_authority),
+            Err(MerkleTreeError::Unauthorized.into())
+        );
+        assert_eq!(authority_of(&tree.data), authority.key);
+
+        process_set_authority(&program_id, &[tree.info(), authority.info()], new_authority)
+            .unwrap();
+        assert_eq!(authority_of(&tree.data), new_authority);
+    }
+
+    #[test]
+    fn hands_over_a_dense_tree() {
+        hands_over(
+            MerkleTreeAccount::space_for(1, 0),
+            |program_id, accounts| {
+                let leaves = vec![LeafInput::Data(b"leaf".to_vec())];
+                process_build_tree(program_id, accounts, TreeConfig::default(), leaves, 0)
+            },
+            |data| MerkleTreeAccount::unpack(data).unwrap().authority,
+        );
+    }
+
+    #[test]
+    fn hands_over_a_sparse_tree() {
+        hands_over(
+            SparseMerkleTreeAccount::LEN,
+            |program_id, accounts| {
+                process_build_sparse_tree(program_id, accounts, TreeConfig::default())
+            },
+            |data| SparseMerkleTreeAccount::unpack(data).unwrap().authority,
+        );
+    }
+
+    #[test]
+    fn hands_over_a_concurrent_tree() {
+        hands_over(
+            ConcurrentMerkleTreeAccount::space_for(3, 4),
+            |program_id, accounts| {
+                process_build_concurrent_tree(program_id, accounts, TreeConfig::default(), 3, 4)
+            },
+            |data| ConcurrentMerkleTreeAccount::unpack(data).unwrap().authority,
+        );
+    }
+
+    #[test]
+    fn hands_over_an_incremental_tree() {
+        hands_over(
+            IncrementalMerkleTreeAccount::space_for(4),
+            |program_id, accounts| {
+                process_build_incremental_tree(program_id, accounts, TreeConfig::default(), 4)
+            },
+            |data| {
+                IncrementalMerkleTreeAccount::unpack(data)
+                    .unwrap()
+                    .authority
+            },
+        );
+    }
+
+    #[test]
+    fn hands_over_a_mountain_range() {
+        hands_over(
+            MerkleMountainRangeAccount::LEN,
+            |program_id, accounts| process_build_mmr(program_id, accounts, TreeConfig::default()),
+            |data| MerkleMountainRangeAccount::unpack(data).unwrap().authority,
+        );
+    }
+}
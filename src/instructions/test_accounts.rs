@@ -0,0 +1,47 @@
+//! Account fixtures for calling instruction handlers directly in tests.
+
+use solana_program::{account_info::AccountInfo, pubkey::Pubkey};
+
+/// Owned storage behind an `AccountInfo`.
+pub struct TestAccount {
+    pub key: Pubkey,
+    pub owner: Pubkey,
+    pub is_signer: bool,
+    pub lamports: u64,
+    pub data: Vec<u8>,
+}
+
+impl TestAccount {
+    /// A zeroed, writable account of `data_len` bytes owned by `owner`.
+    pub fn new(owner: Pubkey, data_len: usize) -> Self {
+        TestAccount {
+            key: Pubkey::new_unique(),
+            owner,
+            is_signer: false,
+            lamports: 0,
+            data: vec![0; data_len],
+        }
+    }
+
+    /// A data-less account signing the transaction.
+    pub fn signer() -> Self {
+        TestAccount {
+            is_signer: true,
+            ..TestAccount::new(Pubkey::default(), 0)
+        }
+    }
+
+    /// Borrows the account as the runtime would pass it to the program.
+    pub fn info(&mut self) -> AccountInfo<'_> {
+        AccountInfo::new(
+            &self.key,
+            self.is_signer,
+            true,
+            &mut self.lamports,
+            &mut self.data,
+            &self.owner,
+            false,
+            0,
+        )
+    }
+}
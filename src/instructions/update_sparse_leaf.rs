@@ -0,0 +1,164 @@
+use solana_program::{
+    account_info::{next_account_info, AccountInfo},
+    entrypoint::ProgramResult,
+    hash::Hash,
+    program_error::ProgramError,
+    pubkey::Pubkey,
+};
+
+use crate::{
+    errors::MerkleTreeError,
+    instructions::check_authority,
+    sparse::{
+        recompute_sparse_root, sparse_leaf_hash, SparseKey, SparseMerkleProof, SPARSE_TREE_DEPTH,
+    },
+    state::SparseMerkleTreeAccount,
+    utils::empty_subtree_hashes,
+};
+
+/// Changes the value under `key` from `old_value` to `new_value`, where `None`
+/// means the key is absent, and updates the root along the key's path.
+pub fn process_update_sparse_leaf(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    key: SparseKey,
+    old_value: Option<Vec<u8>>,
+    new_value: Option<Vec<u8>>,
+    proof: SparseMerkleProof,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let tree_account_info = next_account_info(account_info_iter)?;
+    let authority_info = next_account_info(account_info_iter)?;
+
+    // Ensure the account is owned by the program.
+    if tree_account_info.owner != program_id {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+
+    // Load the sparse tree account.
+    let mut tree_account = SparseMerkleTreeAccount::unpack(&tree_account_info.try_borrow_data()?)?;
+    check_authority(&tree_account.authority, authority_info)?;
+
+    let config = tree_account.config;
+    let empty_hashes = empty_subtree_hashes(&config, SPARSE_TREE_DEPTH);
+    let leaf = |value: &Option<Vec<u8>>| -> Hash {
+        value.as_ref().map_or(empty_hashes[0], |value| {
+            sparse_leaf_hash(&config, &key, value)
+        })
+    };
+
+    // The proof must lead from the current slot content to the current root.
+    let old_root = recompute_sparse_root(&config, &empty_hashes, &key, leaf(&old_value), &proof)?;
+    if old_root != tree_account.root {
+        return Err(MerkleTreeError::InvalidProof.into());
+    }
+
+    // Replay the same path with the new slot content to get the new root.
+    tree_account.root =
+        recompute_sparse_root(&config, &empty_hashes, &key, leaf(&new_value), &proof)?;
+    tree_account.entry_count = match (old_value.is_some(), new_value.is_some()) {
+        (false, true) => tree_account.entry_count.checked_add(1),
+        (true, false) => tree_account.entry_count.checked_sub(1),
+        _ => Some(tree_account.entry_count),
+    }
+    .ok_or(ProgramError::InvalidAccountData)?;
+
+    // Pack the updated SparseMerkleTreeAccount data back into the account.
+    SparseMerkleTreeAccount::pack(tree_account, &mut tree_account_info.try_borrow_mut_data()?)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        instructions::{build_sparse_tree::process_build_sparse_tree, test_accounts::TestAccount},
+        sparse::SparseMerkleTree,
+        state::TreeConfig,
+    };
+
+    #[test]
+    fn inserting_then_deleting_restores_the_empty_root() {
+        let program_id = Pubkey::new_unique();
+        let config = TreeConfig::default();
+        let mut tree = TestAccount::new(program_id, SparseMerkleTreeAccount::LEN);
+        let mut authority = TestAccount::signer();
+        process_build_sparse_tree(&program_id, &[tree.info(), authority.info()], config).unwrap();
+        let empty_root = SparseMerkleTreeAccount::unpack(&tree.data).unwrap().root;
+
+        let keys = [[1; 32], [2; 32]];
+        let mut off_chain = SparseMerkleTree::new(config);
+        assert_eq!(off_chain.root(), empty_root);
+        for key in &keys {
+            let proof = off_chain.proof(key);
+            process_update_sparse_leaf(
+                &program_id,
+                &[tree.info(), authority.info()],
+                *key,
+                None,
+                Some(key.to_vec()),
+                proof,
+            )
+            .unwrap();
+            off_chain.insert(key, key);
+            let tree_account = SparseMerkleTreeAccount::unpack(&tree.data).unwrap();
+            assert_eq!(tree_account.root, off_chain.root());
+        }
+        for key in &keys {
+            let proof = off_chain.proof(key);
+            process_update_sparse_leaf(
+                &program_id,
+                &[tree.info(), authority.info()],
+                *key,
+                Some(key.to_vec()),
+                None,
+                proof,
+            )
+            .unwrap();
+            off_chain.remove(key);
+            let tree_account = SparseMerkleTreeAccount::unpack(&tree.data).unwrap();
+            assert_eq!(tree_account.root, off_chain.root());
+        }
+
+        let tree_account = SparseMerkleTreeAccount::unpack(&tree.data).unwrap();
+        assert_eq!(tree_account.root, empty_root);
+        assert_eq!(tree_account.entry_count, 0);
+    }
+
+    #[test]
+    fn rejects_inserting_a_present_key() {
+        let program_id = Pubkey::new_unique();
+        let config = TreeConfig::default();
+        let mut tree = TestAccount::new(program_id, SparseMerkleTreeAccount::LEN);
+        let mut authority = TestAccount::signer();
+        process_build_sparse_tree(&program_id, &[tree.info(), authority.info()], config).unwrap();
+
+        let key = [1; 32];
+        let mut off_chain = SparseMerkleTree::new(config);
+        let mut insert = |proof| {
+            process_update_sparse_leaf(
+                &program_id,
+                &[tree.info(), authority.info()],
+                key,
+                None,
+                Some(b"value".to_vec()),
+                proof,
+            )
+        };
+        insert(off_chain.proof(&key)).unwrap();
+        off_chain.insert(&key, b"value");
+
+        // Neither the stale proof of absence nor the current proof of the
+        // value shows the key as absent.
+        let stale_proof = SparseMerkleTree::new(config).proof(&key);
+        for proof in [stale_proof, off_chain.proof(&key)] {
+            assert_eq!(insert(proof), Err(MerkleTreeError::InvalidProof.into()));
+        }
+        assert_eq!(
+            SparseMerkleTreeAccount::unpack(&tree.data)
+                .unwrap()
+                .entry_count,
+            1
+        );
+    }
+}
@@ -0,0 +1,82 @@
+//! Decoder for version 1 of the Borsh instruction format.
+//!
+//! Version 1 predates root histories: `BuildTree`, `BuildTreeFromHashes` and
+//! `BuildRootOnlyTree` had no `root_history_size` field. Every other variant is
+//! encoded the same way in both versions.
+
+use borsh::{io, BorshDeserialize};
+use solana_program::{hash::Hash, program_error::ProgramError};
+
+use crate::{instructions::MerkleInstruction, state::TreeConfig};
+
+/// Fields of `BuildTree` in version 1.
+#[derive(BorshDeserialize)]
+struct BuildTree {
+    config: TreeConfig,
+    data: Vec<Vec<u8>>,
+}
+
+/// Fields of `BuildTreeFromHashes` in version 1.
+#[derive(BorshDeserialize)]
+struct BuildTreeFromHashes {
+    config: TreeConfig,
+    leaves: Vec<Hash>,
+}
+
+/// Fields of `BuildRootOnlyTree` in version 1.
+#[derive(BorshDeserialize)]
+struct BuildRootOnlyTree {
+    config: TreeConfig,
+    leaves_prehashed: bool,
+    leaf_count: u32,
+    root: Hash,
+}
+
+/// Unpacks the Borsh encoding of a version 1 instruction, following the
+/// marker and version bytes. Trees built this way keep no root history.
+pub fn unpack(instruction_data: &[u8]) -> Result<MerkleInstruction, ProgramError> {
+    let (tag, fields) = instruction_data
+        .split_first()
+        .ok_or(ProgramError::InvalidInstructionData)?;
+    decode(*tag, fields, instruction_data).map_err(|_| ProgramError::InvalidInstructionData)
+}
+
+/// Decodes the variant `tag`, given its `fields` and the whole encoding.
+fn decode(tag: u8, fields: &[u8], instruction_data: &[u8]) -> io::Result<MerkleInstruction> {
+    let instruction = match tag {
+        0 => {
+            let BuildTree { config, data } = BuildTree::try_from_slice(fields)?;
+            MerkleInstruction::BuildTree {
+                config,
+                data,
+                root_history_size: 0,
+            }
+        }
+        6 => {
+            let BuildTreeFromHashes { config, leaves } =
+                BuildTreeFromHashes::try_from_slice(fields)?;
+            MerkleInstruction::BuildTreeFromHashes {
+                config,
+                leaves,
+                root_history_size: 0,
+            }
+        }
+        10 => {
+            let BuildRootOnlyTree {
+                config,
+                leaves_prehashed,
+                leaf_count,
+                root,
+            } = BuildRootOnlyTree::try_from_slice(fields)?;
+            MerkleInstruction::BuildRootOnlyTree {
+                config,
+                leaves_prehashed,
+                leaf_count,
+                root,
+                root_history_size: 0,
+            }
+        }
+        _ => MerkleInstruction::try_from_slice(instruction_data)?,
+    };
+    Ok(instruction)
+}
@@ -0,0 +1,147 @@
+use solana_program::{
+    account_info::{next_account_info, AccountInfo},
+    entrypoint::ProgramResult,
+    hash::Hash,
+    msg,
+    program_error::ProgramError,
+    pubkey::Pubkey,
+};
+
+use crate::{
+    cpi::{set_verification_result, VerificationResult},
+    state::IncrementalMerkleTreeAccount,
+    utils::{recompute_merkle_root_from_leaf_hash, MerkleHasher},
+};
+
+/// Verifies a proof for the leaf at `leaf_index` of an incremental tree and
+/// reports the outcome via return data.
+pub fn process_verify_incremental_proof(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    leaf_index: u32,
+    leaf_data: Vec<u8>,
+    proof: Vec<Hash>,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let tree_account_info = next_account_info(account_info_iter)?;
+
+    // Ensure the account is owned by the program.
+    if tree_account_info.owner != program_id {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+
+    // Load the incremental tree account.
+    let tree_account = IncrementalMerkleTreeAccount::unpack(&tree_account_info.try_borrow_data()?)?;
+
+    let result = verify(&tree_account, leaf_index, &leaf_data, &proof);
+    match result {
+        VerificationResult::Valid | VerificationResult::ValidHistoricRoot { .. } => {
+            msg!("Incremental proof is valid; leaf is in the tree.")
+        }
+        VerificationResult::InvalidProof => msg!("Incremental proof is INVALID."),
+        VerificationResult::MalformedInput => {
+            msg!("Incremental proof does not match the tree shape.")
+        }
+    }
+
+    set_verification_result(&result)
+}
+
+/// Checks `proof` for `leaf_data` at `leaf_index` against the tree's root.
+fn verify(
+    tree_account: &IncrementalMerkleTreeAccount,
+    leaf_index: u32,
+    leaf_data: &[u8],
+    proof: &[Hash],
+) -> VerificationResult {
+    // The leaf must have been appended, and the proof must span every level
+    // of the tree, empty subtrees included.
+    if u64::from(leaf_index) >= tree_account.leaf_count
+        || proof.len() != tree_account.depth as usize
+    {
+        return VerificationResult::MalformedInput;
+    }
+
+    // Recompute the root from the proof using the tree's hashing scheme.
+    let config = &tree_account.config;
+    match recompute_merkle_root_from_leaf_hash(
+        config,
+        config.hash_leaf(leaf_data),
+        leaf_index,
+        proof,
+    ) {
+        Ok(root) if root == tree_account.root => VerificationResult::Valid,
+        Ok(_) => VerificationResult::InvalidProof,
+        Err(_) => VerificationResult::MalformedInput,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        instructions::{
+            append_incremental_leaves::process_append_incremental_leaves,
+            build_incremental_tree::process_build_incremental_tree, test_accounts::TestAccount,
+        },
+        merkle_tree::IncrementalMerkleTree,
+        state::TreeConfig,
+    };
+
+    #[test]
+    fn verifies_proofs_from_the_off_chain_tree() {
+        let config = TreeConfig {
+            domain_separated: true,
+            ..TreeConfig::default()
+        };
+        for depth in [1, 5, 32] {
+            let program_id = Pubkey::new_unique();
+            let mut tree =
+                TestAccount::new(program_id, IncrementalMerkleTreeAccount::space_for(depth));
+            let mut authority = TestAccount::signer();
+            process_build_incremental_tree(
+                &program_id,
+                &[tree.info(), authority.info()],
+                config,
+                depth,
+            )
+            .unwrap();
+
+            let leaves = vec![b"a".to_vec(), b"b".to_vec()];
+            process_append_incremental_leaves(
+                &program_id,
+                &[tree.info(), authority.info()],
+                leaves.clone(),
+            )
+            .unwrap();
+            let mut off_chain = IncrementalMerkleTree::new(config, depth).unwrap();
+            for leaf in &leaves {
+                off_chain.append(leaf).unwrap();
+            }
+
+            let tree_account = IncrementalMerkleTreeAccount::unpack(&tree.data).unwrap();
+            assert_eq!(tree_account.root, off_chain.root());
+            for (leaf_index, leaf) in leaves.iter().enumerate() {
+                let proof = off_chain.proof(leaf_index as u32).unwrap();
+                assert_eq!(
+                    verify(&tree_account, proof.leaf_index, leaf, &proof.siblings),
+                    VerificationResult::Valid
+                );
+            }
+
+            let proof = off_chain.proof(0).unwrap();
+            assert_eq!(
+                verify(&tree_account, 0, b"b", &proof.siblings),
+                VerificationResult::InvalidProof
+            );
+            assert_eq!(
+                verify(&tree_account, 0, b"a", &proof.siblings[1..]),
+                VerificationResult::MalformedInput
+            );
+            assert_eq!(
+                verify(&tree_account, 2, b"a", &proof.siblings),
+                VerificationResult::MalformedInput
+            );
+        }
+    }
+}
@@ -0,0 +1,52 @@
+use solana_program::{
+    account_info::{next_account_info, AccountInfo},
+    entrypoint::ProgramResult,
+    msg,
+    program_error::ProgramError,
+    pubkey::Pubkey,
+};
+
+use crate::{
+    cpi::{set_verification_result, VerificationResult},
+    mmr::{recompute_mmr_peak, MmrProof},
+    state::MerkleMountainRangeAccount,
+    utils::MerkleHasher,
+};
+
+/// Verifies that `leaf_data` is the leaf at `proof.leaf_index` of a Merkle
+/// mountain range and reports the outcome via return data.
+pub fn process_verify_mmr_proof(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    leaf_data: Vec<u8>,
+    proof: MmrProof,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let mmr_account_info = next_account_info(account_info_iter)?;
+
+    // Ensure the account is owned by the program.
+    if mmr_account_info.owner != program_id {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+
+    // Load the Merkle mountain range account.
+    let mmr_account = MerkleMountainRangeAccount::unpack(&mmr_account_info.try_borrow_data()?)?;
+
+    // Recompute the leaf's peak using the range's hashing scheme.
+    let config = &mmr_account.config;
+    let leaf = config.hash_leaf(&leaf_data);
+    let result = match recompute_mmr_peak(config, mmr_account.leaf_count, leaf, &proof) {
+        Ok((position, peak)) if mmr_account.peaks[position] == peak => VerificationResult::Valid,
+        Ok(_) => VerificationResult::InvalidProof,
+        Err(_) => VerificationResult::MalformedInput,
+    };
+    match result {
+        VerificationResult::Valid | VerificationResult::ValidHistoricRoot { .. } => {
+            msg!("MMR proof is valid; leaf is in the range.")
+        }
+        VerificationResult::InvalidProof => msg!("MMR proof is INVALID."),
+        VerificationResult::MalformedInput => msg!("MMR proof does not match the range shape."),
+    }
+
+    set_verification_result(&result)
+}
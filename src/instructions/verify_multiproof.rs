@@ -0,0 +1,92 @@
+use solana_program::{
+    account_info::{next_account_info, AccountInfo},
+    entrypoint::ProgramResult,
+    hash::Hash,
+    msg,
+    program_error::ProgramError,
+    pubkey::Pubkey,
+};
+
+use crate::{
+    cpi::{set_verification_result, VerificationResult},
+    instructions::LeafInput,
+    state::MerkleTreeAccount,
+    utils::{recompute_root_from_multiproof, MultiProof},
+};
+
+/// Verifies a multiproof for the leaves at `leaf_indices` and reports the
+/// outcome via return data.
+pub fn process_verify_multiproof(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    leaf_indices: Vec<u32>,
+    leaves: Vec<LeafInput>,
+    proof: Vec<Hash>,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let merkle_account_info = next_account_info(account_info_iter)?;
+
+    // Ensure the account is owned by the program.
+    if merkle_account_info.owner != program_id {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+
+    // Load the Merkle tree account.
+    let tree_account = MerkleTreeAccount::unpack(&merkle_account_info.try_borrow_data()?)?;
+
+    let result = verify(&tree_account, leaf_indices, &leaves, proof);
+    match result {
+        VerificationResult::Valid => {
+            msg!("Merkle multiproof is valid; leaves are in the tree.")
+        }
+        VerificationResult::ValidHistoricRoot { root_age } => {
+            msg!(
+                "Merkle multiproof is valid against the root from {} changes ago.",
+                root_age
+            )
+        }
+        VerificationResult::InvalidProof => msg!("Merkle multiproof is INVALID."),
+        VerificationResult::MalformedInput => {
+            msg!("Merkle multiproof does not match the tree shape.")
+        }
+    }
+
+    set_verification_result(&result)
+}
+
+/// Checks the multiproof for `leaves` against the tree's current and historic
+/// roots.
+fn verify(
+    tree_account: &MerkleTreeAccount,
+    leaf_indices: Vec<u32>,
+    leaves: &[LeafInput],
+    siblings: Vec<Hash>,
+) -> VerificationResult {
+    // Every leaf must be encoded like the tree's leaves.
+    if leaves
+        .iter()
+        .any(|leaf| leaf.is_prehashed() != tree_account.leaves_prehashed)
+    {
+        return VerificationResult::MalformedInput;
+    }
+
+    // Recompute the Merkle root from the proof using the tree's hashing scheme.
+    let config = &tree_account.config;
+    let proof = MultiProof {
+        leaf_indices,
+        leaves: leaves.iter().map(|leaf| leaf.to_hash(config)).collect(),
+        siblings,
+    };
+
+    // The proof's shape depends on the leaf count, so recompute the root for
+    // each known root; the newest matching one wins.
+    let mut result = VerificationResult::MalformedInput;
+    for (known, root_age) in tree_account.known_roots().zip(0..=u8::MAX) {
+        match recompute_root_from_multiproof(config, known.leaf_count, &proof) {
+            Ok(root) if root == known.root => return VerificationResult::valid(root_age),
+            Ok(_) => result = VerificationResult::InvalidProof,
+            Err(_) => {}
+        }
+    }
+    result
+}
@@ -4,16 +4,45 @@ use solana_program::{
     hash::Hash,
     msg,
     program_error::ProgramError,
-    program_pack::Pack,
     pubkey::Pubkey,
 };
 
 use crate::{
-    errors::MerkleTreeError, state::MerkleTreeAccount, utils::recompute_merkle_root_from_proof,
+    cpi::{set_verification_result, VerificationResult},
+    instructions::LeafInput,
+    state::{HistoricRoot, MerkleTreeAccount},
+    utils::{leaf_path, recompute_merkle_root_from_leaf_hash},
 };
 
-/// Verifies a Merkle proof for a given leaf.
+/// Verifies a Merkle proof for the leaf at `leaf_index` and reports the
+/// outcome via return data.
 pub fn process_verify_proof(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    leaf_index: u32,
+    leaf: LeafInput,
+    proof: Vec<Hash>,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let merkle_account_info = next_account_info(account_info_iter)?;
+
+    // Ensure the account is owned by the program.
+    if merkle_account_info.owner != program_id {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+
+    // Load the Merkle tree account.
+    let tree_account = MerkleTreeAccount::unpack(&merkle_account_info.try_borrow_data()?)?;
+
+    report(verify(&tree_account, leaf_index, &leaf, &proof))
+}
+
+/// Verifies a proof in the original `VerifyProof` format, which carried no
+/// leaf index, and reports the outcome like `process_verify_proof`.
+///
+/// The leaf is looked up among the stored leaves and the proof checked at
+/// each index holding it, so root-only trees always report `InvalidProof`.
+pub fn process_verify_unindexed_proof(
     program_id: &Pubkey,
     accounts: &[AccountInfo],
     leaf_data: Vec<u8>,
@@ -30,15 +59,251 @@ pub fn process_verify_proof(
     // Load the Merkle tree account.
     let tree_account = MerkleTreeAccount::unpack(&merkle_account_info.try_borrow_data()?)?;
 
-    // Recompute the Merkle root from the proof.
-    let recomputed_root = recompute_merkle_root_from_proof(&leaf_data, &proof)?;
+    report(verify_unindexed(
+        &tree_account,
+        &LeafInput::Data(leaf_data),
+        &proof,
+    ))
+}
+
+/// Logs `result` and sets it as the return data.
+fn report(result: VerificationResult) -> ProgramResult {
+    match result {
+        VerificationResult::Valid => msg!("Merkle proof is valid; leaf is in the tree."),
+        VerificationResult::ValidHistoricRoot { root_age } => {
+            msg!(
+                "Merkle proof is valid against the root from {} changes ago.",
+                root_age
+            )
+        }
+        VerificationResult::InvalidProof => msg!("Merkle proof is INVALID."),
+        VerificationResult::MalformedInput => msg!("Merkle proof does not match the tree shape."),
+    }
+
+    set_verification_result(&result)
+}
+
+/// Checks `proof` for `leaf` at every index holding it, keeping the first
+/// valid outcome, or the last one if none is valid.
+fn verify_unindexed(
+    tree_account: &MerkleTreeAccount,
+    leaf: &LeafInput,
+    proof: &[Hash],
+) -> VerificationResult {
+    // A leaf encoded differently from the stored ones could never be found.
+    if leaf.is_prehashed() != tree_account.leaves_prehashed {
+        return VerificationResult::MalformedInput;
+    }
+
+    let leaf_hash = leaf.to_hash(&tree_account.config);
+    let leaf_indices = (0..)
+        .zip(&tree_account.leaves)
+        .filter(|(_, stored_leaf)| **stored_leaf == leaf_hash)
+        .map(|(leaf_index, _)| leaf_index);
+    let mut result = VerificationResult::InvalidProof;
+    for leaf_index in leaf_indices {
+        result = verify(tree_account, leaf_index, leaf, proof);
+        if result.is_valid() {
+            break;
+        }
+    }
+    result
+}
+
+/// Checks `proof` for `leaf` at `leaf_index` against the tree's current and
+/// historic roots.
+fn verify(
+    tree_account: &MerkleTreeAccount,
+    leaf_index: u32,
+    leaf: &LeafInput,
+    proof: &[Hash],
+) -> VerificationResult {
+    // The leaf must be encoded like the tree's leaves.
+    if leaf.is_prehashed() != tree_account.leaves_prehashed {
+        return VerificationResult::MalformedInput;
+    }
+
+    // The leaf must exist, and the proof must span every level of its path in
+    // the tree, as it was when it had the given root.
+    let config = &tree_account.config;
+    let path_in = |known: &HistoricRoot| {
+        leaf_path(config, leaf_index, known.leaf_count).filter(|(_, levels)| proof.len() == *levels)
+    };
+    let fits = |known: &HistoricRoot| path_in(known).is_some();
+    let Some((path, _)) = tree_account.known_roots().find_map(|known| path_in(&known)) else {
+        return VerificationResult::MalformedInput;
+    };
+
+    // Recompute the Merkle root from the proof using the tree's hashing scheme.
+    // Paths to the same leaf only differ between leaf counts in complete trees,
+    // whose sorted pairs do not depend on the path directions, so the root is
+    // the same whichever fitting root the path was taken from.
+    let root = match recompute_merkle_root_from_leaf_hash(config, leaf.to_hash(config), path, proof)
+    {
+        Ok(root) => root,
+        Err(_) => return VerificationResult::MalformedInput,
+    };
+
+    // The newest matching root wins.
+    tree_account
+        .known_roots()
+        .zip(0..=u8::MAX)
+        .find(|(known, _)| known.root == root && fits(known))
+        .map_or(VerificationResult::InvalidProof, |(_, root_age)| {
+            VerificationResult::valid(root_age)
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        instructions::{
+            append_leaves::process_append_leaves, build_tree::process_build_tree, legacy,
+            process_instruction, replace_leaf::process_replace_leaf, test_accounts::TestAccount,
+        },
+        merkle_tree::MerkleTree,
+        state::TreeConfig,
+    };
+
+    /// Replaces `data[index]` with `new_leaf_data` in `tree`, which holds `data`.
+    fn replace(
+        program_id: &Pubkey,
+        [tree, authority]: &mut [TestAccount; 2],
+        data: &mut [Vec<u8>],
+        index: u32,
+        new_leaf_data: &[u8],
+    ) {
+        let proof = MerkleTree::new(TreeConfig::default(), data)
+            .unwrap()
+            .proof(index)
+            .unwrap();
+        process_replace_leaf(
+            program_id,
+            &[tree.info(), authority.info()],
+            index,
+            proof.leaf,
+            LeafInput::Data(new_leaf_data.to_vec()),
+            proof.siblings,
+        )
+        .unwrap();
+        data[index as usize] = new_leaf_data.to_vec();
+    }
+
+    #[test]
+    fn verifies_proofs_against_historic_roots() {
+        let program_id = Pubkey::new_unique();
+        let mut accounts = [
+            TestAccount::new(program_id, MerkleTreeAccount::space_for(4, 2)),
+            TestAccount::signer(),
+        ];
+        let mut data = vec![b"a".to_vec(), b"b".to_vec(), b"c".to_vec()];
+        let [tree, authority] = &mut accounts;
+        process_build_tree(
+            &program_id,
+            &[tree.info(), authority.info()],
+            TreeConfig::default(),
+            data.iter().cloned().map(LeafInput::Data).collect(),
+            2,
+        )
+        .unwrap();
+
+        let original = MerkleTree::new(TreeConfig::default(), &data).unwrap();
+        let proof = original.proof(0).unwrap();
+        let leaf = LeafInput::Data(b"a".to_vec());
+        let verify_original = |tree: &TestAccount| {
+            let tree_account = MerkleTreeAccount::unpack(&tree.data).unwrap();
+            verify(&tree_account, 0, &leaf, &proof.siblings)
+        };
+        assert_eq!(verify_original(&accounts[0]), VerificationResult::Valid);
+
+        // Each change moves the previous root into the history.
+        let [tree, authority] = &mut accounts;
+        process_append_leaves(
+            &program_id,
+            &[tree.info(), authority.info()],
+            vec![LeafInput::Data(b"d".to_vec())],
+        )
+        .unwrap();
+        data.push(b"d".to_vec());
+        assert_eq!(
+            verify_original(&accounts[0]),
+            VerificationResult::ValidHistoricRoot { root_age: 1 }
+        );
+
+        replace(&program_id, &mut accounts, &mut data, 3, b"e");
+        assert_eq!(
+            verify_original(&accounts[0]),
+            VerificationResult::ValidHistoricRoot { root_age: 2 }
+        );
+        let current = MerkleTree::new(TreeConfig::default(), &data).unwrap();
+        let tree_account = MerkleTreeAccount::unpack(&accounts[0].data).unwrap();
+        assert_eq!(
+            verify(&tree_account, 0, &leaf, &current.proof(0).unwrap().siblings),
+            VerificationResult::Valid
+        );
+
+        // A third change evicts the original root from the two-root history.
+        replace(&program_id, &mut accounts, &mut data, 3, b"f");
+        assert_eq!(
+            verify_original(&accounts[0]),
+            VerificationResult::InvalidProof
+        );
+    }
+
+    #[test]
+    fn verifies_proofs_in_the_original_format() {
+        // Build the tree from the original `BuildTree` wire bytes.
+        let program_id = Pubkey::new_unique();
+        let mut tree = TestAccount::new(program_id, MerkleTreeAccount::space_for(3, 0));
+        let mut authority = TestAccount::signer();
+        let data = [b"a".to_vec(), b"bb".to_vec(), b"a".to_vec()];
+        process_instruction(
+            &program_id,
+            &[tree.info(), authority.info()],
+            &[0, 1, b'a', 2, b'b', b'b', 1, b'a'],
+        )
+        .unwrap();
+        let tree_account = MerkleTreeAccount::unpack(&tree.data).unwrap();
+        let off_chain = MerkleTree::new(TreeConfig::default(), &data).unwrap();
+
+        let original_verify_proof = |leaf_data: &[u8], proof: &[Hash]| {
+            let mut instruction_data = vec![2, leaf_data.len() as u8];
+            instruction_data.extend(leaf_data);
+            instruction_data.extend(proof.iter().flat_map(|hash| hash.to_bytes()));
+            let legacy::UnindexedVerifyProof { leaf_data, proof } =
+                legacy::unpack_verify_proof(&instruction_data)
+                    .unwrap()
+                    .unwrap();
+            verify_unindexed(&tree_account, &LeafInput::Data(leaf_data), &proof)
+        };
+
+        // "a" is stored twice; each proof is valid at one of its indices.
+        for (leaf_index, leaf_data) in data.iter().enumerate() {
+            let proof = off_chain.proof(leaf_index as u32).unwrap();
+            assert_eq!(
+                original_verify_proof(leaf_data, &proof.siblings),
+                VerificationResult::Valid
+            );
+        }
+
+        let proof = off_chain.proof(1).unwrap();
+        assert_eq!(
+            original_verify_proof(b"a", &proof.siblings),
+            VerificationResult::InvalidProof
+        );
+        assert_eq!(
+            original_verify_proof(b"c", &proof.siblings),
+            VerificationResult::InvalidProof
+        );
+        assert_eq!(
+            original_verify_proof(b"bb", &proof.siblings[1..]),
+            VerificationResult::MalformedInput
+        );
 
-    // Compare with the stored root.
-    if recomputed_root == tree_account.root {
-        msg!("Merkle proof is valid; leaf is in the tree.");
-        Ok(())
-    } else {
-        msg!("Merkle proof is INVALID.");
-        Err(MerkleTreeError::InvalidProof.into())
+        // The whole instruction runs through the program entry point too.
+        let mut instruction_data = vec![2, 2, b'b', b'b'];
+        instruction_data.extend(proof.siblings.iter().flat_map(|hash| hash.to_bytes()));
+        process_instruction(&program_id, &[tree.info()], &instruction_data).unwrap();
     }
 }
@@ -0,0 +1,136 @@
+use solana_program::{
+    account_info::{next_account_info, AccountInfo},
+    entrypoint::ProgramResult,
+    msg,
+    program_error::ProgramError,
+    pubkey::Pubkey,
+};
+
+use crate::{
+    cpi::{set_verification_result, VerificationResult},
+    sparse::{
+        recompute_sparse_root, sparse_leaf_hash, SparseKey, SparseMerkleProof, SPARSE_TREE_DEPTH,
+    },
+    state::SparseMerkleTreeAccount,
+    utils::empty_subtree_hashes,
+};
+
+/// Verifies that `key` holds `value`, or is absent when `value` is `None`, and
+/// reports the outcome via return data.
+pub fn process_verify_sparse_proof(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    key: SparseKey,
+    value: Option<Vec<u8>>,
+    proof: SparseMerkleProof,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let merkle_account_info = next_account_info(account_info_iter)?;
+
+    // Ensure the account is owned by the program.
+    if merkle_account_info.owner != program_id {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+
+    // Load the sparse tree account.
+    let tree_account = SparseMerkleTreeAccount::unpack(&merkle_account_info.try_borrow_data()?)?;
+
+    let result = verify(&tree_account, &key, value.as_deref(), &proof);
+    match (result, value.is_some()) {
+        (VerificationResult::Valid | VerificationResult::ValidHistoricRoot { .. }, true) => {
+            msg!("Sparse proof is valid; key is in the tree.")
+        }
+        (VerificationResult::Valid | VerificationResult::ValidHistoricRoot { .. }, false) => {
+            msg!("Sparse proof is valid; key is absent.")
+        }
+        (VerificationResult::InvalidProof, _) => msg!("Sparse proof is INVALID."),
+        (VerificationResult::MalformedInput, _) => msg!("Sparse proof is malformed."),
+    }
+
+    set_verification_result(&result)
+}
+
+/// Checks `proof` for `key`'s slot holding `value`, or being empty when
+/// `value` is `None`, against the tree's root.
+fn verify(
+    tree_account: &SparseMerkleTreeAccount,
+    key: &SparseKey,
+    value: Option<&[u8]>,
+    proof: &SparseMerkleProof,
+) -> VerificationResult {
+    // Recompute the root from the slot content using the tree's hashing scheme.
+    let config = &tree_account.config;
+    let empty_hashes = empty_subtree_hashes(config, SPARSE_TREE_DEPTH);
+    let leaf = value.map_or(empty_hashes[0], |value| {
+        sparse_leaf_hash(config, key, value)
+    });
+    match recompute_sparse_root(config, &empty_hashes, key, leaf, proof) {
+        Ok(root) if root == tree_account.root => VerificationResult::Valid,
+        Ok(_) => VerificationResult::InvalidProof,
+        Err(_) => VerificationResult::MalformedInput,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        instructions::{
+            build_sparse_tree::process_build_sparse_tree, test_accounts::TestAccount,
+            update_sparse_leaf::process_update_sparse_leaf,
+        },
+        sparse::SparseMerkleTree,
+        state::TreeConfig,
+    };
+
+    #[test]
+    fn proves_membership_and_absence() {
+        let program_id = Pubkey::new_unique();
+        let config = TreeConfig::default();
+        let mut tree = TestAccount::new(program_id, SparseMerkleTreeAccount::LEN);
+        let mut authority = TestAccount::signer();
+        process_build_sparse_tree(&program_id, &[tree.info(), authority.info()], config).unwrap();
+
+        let (present, absent) = ([1; 32], [2; 32]);
+        let mut off_chain = SparseMerkleTree::new(config);
+        process_update_sparse_leaf(
+            &program_id,
+            &[tree.info(), authority.info()],
+            present,
+            None,
+            Some(b"value".to_vec()),
+            off_chain.proof(&present),
+        )
+        .unwrap();
+        off_chain.insert(&present, b"value");
+        let tree_account = SparseMerkleTreeAccount::unpack(&tree.data).unwrap();
+        assert_eq!(tree_account.root, off_chain.root());
+
+        let membership = off_chain.proof(&present);
+        let absence = off_chain.proof(&absent);
+        let valid = VerificationResult::Valid;
+        assert_eq!(
+            verify(&tree_account, &present, Some(b"value"), &membership),
+            valid
+        );
+        assert_eq!(verify(&tree_account, &absent, None, &absence), valid);
+
+        // Neither proof holds for the other key, nor for the other content.
+        assert_eq!(
+            verify(&tree_account, &absent, Some(b"value"), &membership),
+            VerificationResult::InvalidProof
+        );
+        assert_eq!(
+            verify(&tree_account, &present, None, &absence),
+            VerificationResult::InvalidProof
+        );
+        assert_eq!(
+            verify(&tree_account, &present, Some(b"other"), &membership),
+            VerificationResult::InvalidProof
+        );
+        assert_eq!(
+            verify(&tree_account, &present, None, &membership),
+            VerificationResult::InvalidProof
+        );
+    }
+}
@@ -1,18 +1,12 @@
-use solana_program::{
-    account_info::AccountInfo, entrypoint, entrypoint::ProgramResult, pubkey::Pubkey,
-};
-
-mod errors;
-mod instructions;
-mod processor;
-mod state;
-mod utils;
-
-entrypoint!(process_instruction);
-fn process_instruction(
-    program_id: &Pubkey,
-    accounts: &[AccountInfo],
-    instruction_data: &[u8],
-) -> ProgramResult {
-    processor::Processor::process(program_id, accounts, instruction_data)
-}
+pub mod cpi;
+#[cfg(not(feature = "no-entrypoint"))]
+mod entrypoint;
+pub mod errors;
+pub mod instruction;
+pub mod instructions;
+pub mod merkle_tree;
+pub mod mmr;
+pub mod processor;
+pub mod sparse;
+pub mod state;
+pub mod utils;
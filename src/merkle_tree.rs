@@ -0,0 +1,387 @@
+use solana_program::{hash::Hash, program_error::ProgramError};
+
+use crate::{
+    errors::MerkleTreeError,
+    state::{TreeConfig, MAX_INCREMENTAL_TREE_DEPTH},
+    utils::{
+        empty_subtree_hashes, hash_leaf_level, hash_level, push_leaf_sibling,
+        push_multiproof_leaf_siblings, push_multiproof_siblings,
+        recompute_merkle_root_from_leaf_hash, recompute_root_from_multiproof, recompute_tree_root,
+        sibling_at, tree_depth, MerkleHasher, MerkleProof, MultiProof,
+    },
+};
+
+/// An in-memory Merkle tree for computing roots and proofs off-chain.
+///
+/// It hashes exactly like the on-chain program when built with the same
+/// hasher, which is the tree's `TreeConfig` by default. This is how proofs are
+/// obtained for root-only trees, which do not store their leaves.
+#[derive(Clone, Debug)]
+pub struct MerkleTree<H: MerkleHasher = TreeConfig> {
+    hasher: H,
+
+    /// Every level of the tree, from the leaf hashes up to the root. In a
+    /// complete tree, the leaves above the bottom level are repeated at the
+    /// start of the second level.
+    levels: Vec<Vec<Hash>>,
+}
+
+impl<H: MerkleHasher> MerkleTree<H> {
+    /// Builds a tree by hashing each leaf's raw data with `hasher`.
+    pub fn new<T: AsRef<[u8]>>(hasher: H, leaves: &[T]) -> Result<Self, ProgramError> {
+        let leaves = leaves
+            .iter()
+            .map(|leaf| hasher.hash_leaf(leaf.as_ref()))
+            .collect();
+        Self::from_leaf_hashes(hasher, leaves)
+    }
+
+    /// Builds a tree from leaf hashes, which are used as they are.
+    pub fn from_leaf_hashes(hasher: H, leaves: Vec<Hash>) -> Result<Self, ProgramError> {
+        if leaves.is_empty() {
+            return Err(MerkleTreeError::EmptyTree.into());
+        }
+        if u32::try_from(leaves.len()).is_err() {
+            return Err(MerkleTreeError::TreeCapacityExceeded.into());
+        }
+
+        let mut levels = vec![leaves];
+        while let Some(level) = levels.last().filter(|level| level.len() > 1) {
+            let next_level = if levels.len() == 1 {
+                hash_leaf_level(&hasher, level)
+            } else {
+                hash_level(&hasher, level)
+            };
+            levels.push(next_level);
+        }
+
+        Ok(Self { hasher, levels })
+    }
+
+    /// The Merkle root.
+    pub fn root(&self) -> Hash {
+        self.levels[self.levels.len() - 1][0]
+    }
+
+    /// The leaf hashes, in order.
+    pub fn leaves(&self) -> &[Hash] {
+        &self.levels[0]
+    }
+
+    /// Number of leaves, as stored in a tree account's `leaf_count`.
+    pub fn leaf_count(&self) -> u32 {
+        self.leaves().len() as u32
+    }
+
+    /// Number of levels above the leaves, as stored in a tree account's `depth`.
+    pub fn depth(&self) -> u8 {
+        tree_depth(self.leaves().len())
+    }
+
+    /// Builds the proof for the leaf at `leaf_index`, in the same form as the
+    /// one returned by the `BuildProof` instruction.
+    pub fn proof(&self, leaf_index: u32) -> Result<MerkleProof, ProgramError> {
+        let leaf = *self
+            .leaves()
+            .get(leaf_index as usize)
+            .ok_or(MerkleTreeError::InvalidLeafIndex)?;
+
+        let mut siblings = Vec::with_capacity(self.levels.len() - 1);
+        if let [leaves, levels @ .., _root] = &self.levels[..] {
+            let mut index =
+                push_leaf_sibling(&self.hasher, leaves, leaf_index as usize, &mut siblings);
+            for level in levels {
+                siblings.push(sibling_at(level, index));
+                index /= 2;
+            }
+        }
+
+        Ok(MerkleProof {
+            leaf_index,
+            leaf,
+            siblings,
+        })
+    }
+
+    /// Checks `proof` against this tree's root, applying the same rules as the
+    /// `VerifyProof` instruction.
+    pub fn verify(&self, proof: &MerkleProof) -> bool {
+        recompute_tree_root(
+            &self.hasher,
+            proof.leaf,
+            proof.leaf_index,
+            self.leaf_count(),
+            &proof.siblings,
+        )
+        .is_ok_and(|root| root == self.root())
+    }
+
+    /// Builds a multiproof for the leaves at `leaf_indices`, in any order, in
+    /// the form expected by the `VerifyMultiProof` instruction.
+    pub fn multiproof(&self, leaf_indices: &[u32]) -> Result<MultiProof, ProgramError> {
+        let mut indices = leaf_indices.to_vec();
+        indices.sort_unstable();
+        indices.dedup();
+        let leaves = indices
+            .iter()
+            .map(|&index| self.leaves().get(index as usize).copied())
+            .collect::<Option<Vec<_>>>()
+            .filter(|leaves| !leaves.is_empty())
+            .ok_or(MerkleTreeError::InvalidLeafIndex)?;
+
+        let mut siblings = Vec::new();
+        let mut known = indices
+            .iter()
+            .map(|&index| index as usize)
+            .collect::<Vec<_>>();
+        if let [leaves, levels @ .., _root] = &self.levels[..] {
+            known = push_multiproof_leaf_siblings(&self.hasher, leaves, &known, &mut siblings);
+            for level in levels {
+                known = push_multiproof_siblings(level, &known, &mut siblings);
+            }
+        }
+
+        Ok(MultiProof {
+            leaf_indices: indices,
+            leaves,
+            siblings,
+        })
+    }
+
+    /// Checks a multiproof against this tree's root.
+    pub fn verify_multiproof(&self, proof: &MultiProof) -> bool {
+        recompute_root_from_multiproof(&self.hasher, self.leaf_count(), proof)
+            .is_ok_and(|root| root == self.root())
+    }
+}
+
+/// An in-memory incremental Merkle tree, mirroring an
+/// `IncrementalMerkleTreeAccount` so that proofs can be built for its leaves.
+///
+/// The tree has `2^depth` leaf slots, those not appended yet holding empty
+/// subtrees. Only the nodes above appended leaves are kept, so memory grows
+/// with the number of leaves rather than with the capacity.
+#[derive(Clone, Debug)]
+pub struct IncrementalMerkleTree<H: MerkleHasher = TreeConfig> {
+    hasher: H,
+
+    /// Hashes of empty subtrees, indexed by height.
+    empty_hashes: Vec<Hash>,
+
+    /// At each height from the leaves up to the root, the nodes with at least
+    /// one appended leaf beneath them.
+    levels: Vec<Vec<Hash>>,
+}
+
+impl<H: MerkleHasher> IncrementalMerkleTree<H> {
+    /// Creates an empty tree of `depth` levels, like `BuildIncrementalTree`.
+    pub fn new(hasher: H, depth: u8) -> Result<Self, ProgramError> {
+        if depth == 0 || depth > MAX_INCREMENTAL_TREE_DEPTH {
+            return Err(ProgramError::InvalidArgument);
+        }
+
+        let empty_hashes = empty_subtree_hashes(&hasher, depth as usize);
+        Ok(Self {
+            hasher,
+            empty_hashes,
+            levels: vec![Vec::new(); depth as usize + 1],
+        })
+    }
+
+    /// The Merkle root, as stored in the tree account.
+    pub fn root(&self) -> Hash {
+        let depth = self.depth() as usize;
+        self.levels[depth]
+            .first()
+            .copied()
+            .unwrap_or(self.empty_hashes[depth])
+    }
+
+    /// The appended leaf hashes, in order.
+    pub fn leaves(&self) -> &[Hash] {
+        &self.levels[0]
+    }
+
+    /// Number of leaves appended so far, as stored in the tree account.
+    pub fn leaf_count(&self) -> u64 {
+        self.leaves().len() as u64
+    }
+
+    /// Number of levels above the leaves.
+    pub fn depth(&self) -> u8 {
+        (self.levels.len() - 1) as u8
+    }
+
+    /// Hashes `leaf_data` and appends it, like `AppendIncrementalLeaves`.
+    pub fn append(&mut self, leaf_data: &[u8]) -> Result<(), ProgramError> {
+        let leaf = self.hasher.hash_leaf(leaf_data);
+        self.append_leaf_hash(leaf)
+    }
+
+    /// Appends a leaf hash, failing once the tree holds `2^depth` leaves.
+    pub fn append_leaf_hash(&mut self, leaf: Hash) -> Result<(), ProgramError> {
+        let depth = self.depth() as usize;
+        if self.leaf_count() >= 1 << depth {
+            return Err(MerkleTreeError::TreeCapacityExceeded.into());
+        }
+
+        // Rehash the new leaf's ancestors; a missing right child is empty.
+        self.levels[0].push(leaf);
+        let mut index = self.levels[0].len() - 1;
+        for height in 0..depth {
+            let parent_index = index / 2;
+            let level = &self.levels[height];
+            let left = level[2 * parent_index];
+            let right = level
+                .get(2 * parent_index + 1)
+                .copied()
+                .unwrap_or(self.empty_hashes[height]);
+            let parent = self.hasher.hash_node(&left, &right);
+
+            let parents = &mut self.levels[height + 1];
+            match parents.get_mut(parent_index) {
+                Some(node) => *node = parent,
+                None => parents.push(parent),
+            }
+            index = parent_index;
+        }
+
+        Ok(())
+    }
+
+    /// Builds the proof for the leaf at `leaf_index`, with one sibling per
+    /// level, in the form expected by the `VerifyIncrementalProof`
+    /// instruction. Appending more leaves makes the proof stale.
+    pub fn proof(&self, leaf_index: u32) -> Result<MerkleProof, ProgramError> {
+        let leaf = *self
+            .leaves()
+            .get(leaf_index as usize)
+            .ok_or(MerkleTreeError::InvalidLeafIndex)?;
+
+        let depth = self.depth() as usize;
+        let mut index = leaf_index as usize;
+        let mut siblings = Vec::with_capacity(depth);
+        for (level, empty) in self.levels[..depth].iter().zip(&self.empty_hashes) {
+            siblings.push(level.get(index ^ 1).copied().unwrap_or(*empty));
+            index /= 2;
+        }
+
+        Ok(MerkleProof {
+            leaf_index,
+            leaf,
+            siblings,
+        })
+    }
+
+    /// Checks `proof` against this tree's root, applying the same rules as the
+    /// `VerifyIncrementalProof` instruction.
+    pub fn verify(&self, proof: &MerkleProof) -> bool {
+        if u64::from(proof.leaf_index) >= self.leaf_count()
+            || proof.siblings.len() != self.depth() as usize
+        {
+            return false;
+        }
+
+        recompute_merkle_root_from_leaf_hash(
+            &self.hasher,
+            proof.leaf,
+            proof.leaf_index,
+            &proof.siblings,
+        )
+        .is_ok_and(|root| root == self.root())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        state::{HashFunction, NodeHashing},
+        utils::{build_merkle_root, build_proof},
+    };
+
+    #[test]
+    fn every_layout_proves_every_subset_of_leaves() {
+        let configs = [
+            TreeConfig::default(),
+            TreeConfig {
+                hash_function: HashFunction::Keccak256,
+                node_hashing: NodeHashing::SortedPair,
+                domain_separated: false,
+            },
+        ];
+        for config in configs {
+            for count in 1..=9u32 {
+                let leaves = (0..count).map(|i| format!("leaf {i}")).collect::<Vec<_>>();
+                let tree = MerkleTree::new(config, &leaves).unwrap();
+                assert_eq!(
+                    tree.root(),
+                    build_merkle_root(&config, tree.leaves()).unwrap()
+                );
+
+                for leaf_index in 0..count {
+                    let proof = tree.proof(leaf_index).unwrap();
+                    let siblings = build_proof(&config, leaf_index as usize, tree.leaves());
+                    assert_eq!(proof.siblings, siblings.unwrap());
+                    assert!(tree.verify(&proof), "{config:?}, {count} leaves");
+                }
+
+                for subset in 1..1u32 << count {
+                    let indices = (0..count)
+                        .filter(|i| subset & 1 << i != 0)
+                        .collect::<Vec<_>>();
+                    let proof = tree.multiproof(&indices).unwrap();
+                    assert!(
+                        tree.verify_multiproof(&proof),
+                        "{config:?}, {count} leaves, indices {indices:?}"
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn incremental_tree_matches_a_padded_dense_tree() {
+        let config = TreeConfig::default();
+        let depth = 3;
+        let mut tree = IncrementalMerkleTree::new(config, depth).unwrap();
+        let mut leaves = Vec::new();
+
+        for i in 0..=8 {
+            let mut padded = leaves.clone();
+            padded.resize(1 << depth, config.empty_node());
+            let dense = MerkleTree::from_leaf_hashes(config, padded).unwrap();
+            assert_eq!(tree.root(), dense.root(), "{i} leaves");
+
+            for leaf_index in 0..tree.leaf_count() as u32 {
+                let proof = tree.proof(leaf_index).unwrap();
+                assert_eq!(proof, dense.proof(leaf_index).unwrap());
+                assert!(tree.verify(&proof));
+            }
+
+            let leaf = format!("leaf {i}");
+            if i < 8 {
+                tree.append(leaf.as_bytes()).unwrap();
+                leaves.push(config.hash_leaf(leaf.as_bytes()));
+            } else {
+                assert_eq!(
+                    tree.append(leaf.as_bytes()),
+                    Err(MerkleTreeError::TreeCapacityExceeded.into())
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn incremental_proofs_go_stale_after_an_append() {
+        let mut tree = IncrementalMerkleTree::new(TreeConfig::default(), 32).unwrap();
+        tree.append(b"a").unwrap();
+        let proof = tree.proof(0).unwrap();
+        assert_eq!(proof.siblings.len(), 32);
+        assert!(tree.verify(&proof));
+
+        tree.append(b"b").unwrap();
+        assert!(!tree.verify(&proof));
+        assert!(tree.verify(&tree.proof(0).unwrap()));
+    }
+}
@@ -0,0 +1,292 @@
+//! Merkle mountain ranges: append-only accumulators made of perfect binary
+//! trees ("peaks").
+//!
+//! A range of `n` leaves has one peak of `2^h` leaves for every bit `h` set in
+//! `n`, the highest first. Appending a leaf merges the peaks of equal height,
+//! like adding one to a binary counter. A leaf's proof only reaches the top of
+//! its peak; when that peak is later merged, `upgrade_mmr_proof` extends the
+//! proof with the siblings it was merged with, so old proofs stay valid
+//! prefixes of new ones.
+
+use borsh::{BorshDeserialize, BorshSerialize};
+use solana_program::{hash::Hash, program_error::ProgramError};
+
+use crate::{
+    errors::MerkleTreeError,
+    state::TreeConfig,
+    utils::{build_proof, recompute_merkle_root_from_leaf_hash, MerkleHasher},
+};
+
+/// Largest number of peaks a range can have, one per bit of its `u32` leaf
+/// count.
+pub const MAX_MMR_PEAKS: usize = u32::BITS as usize;
+
+/// Proof that a leaf belongs to one of a range's peaks.
+#[derive(BorshSerialize, BorshDeserialize, Clone, Debug, Default, PartialEq)]
+pub struct MmrProof {
+    /// Index of the proven leaf in the whole range.
+    pub leaf_index: u32,
+
+    /// Sibling hashes inside the leaf's peak, from the leaf level up.
+    pub siblings: Vec<Hash>,
+}
+
+/// Finds the peak holding leaf `leaf_index` of a range of `leaf_count` leaves,
+/// returning its position among the peaks, its height and the index of the
+/// first leaf under it.
+fn locate_peak(leaf_count: u32, leaf_index: u32) -> Option<(usize, usize, u32)> {
+    if leaf_index >= leaf_count {
+        return None;
+    }
+
+    let mut start = 0;
+    for (position, height) in (0..u32::BITS)
+        .rev()
+        .filter(|height| (leaf_count >> height) & 1 == 1)
+        .enumerate()
+    {
+        let size = 1 << height;
+        if leaf_index - start < size {
+            return Some((position, height as usize, start));
+        }
+        start += size;
+    }
+    None
+}
+
+/// Appends `leaf` to the `peaks` of a range of `leaf_count` leaves, merging
+/// every peak of equal height on the way up.
+pub fn append_to_peaks<H: MerkleHasher>(
+    hasher: &H,
+    peaks: &mut Vec<Hash>,
+    leaf_count: u32,
+    leaf: Hash,
+) -> Result<(), ProgramError> {
+    if leaf_count == u32::MAX {
+        return Err(MerkleTreeError::TreeCapacityExceeded.into());
+    }
+    if peaks.len() != leaf_count.count_ones() as usize {
+        return Err(ProgramError::InvalidArgument);
+    }
+
+    // Each set low bit of the count is a peak of the new leaf's height so far.
+    let mut node = leaf;
+    for _ in 0..leaf_count.trailing_ones() {
+        let left = peaks.pop().ok_or(ProgramError::InvalidArgument)?;
+        node = hasher.hash_node(&left, &node);
+    }
+    peaks.push(node);
+    Ok(())
+}
+
+/// Bags `peaks` into a single root, folding them from the right. A range
+/// without leaves has `MerkleHasher::empty_node` as its root.
+pub fn bag_peaks<H: MerkleHasher>(hasher: &H, peaks: &[Hash]) -> Hash {
+    let Some((last, rest)) = peaks.split_last() else {
+        return hasher.empty_node();
+    };
+    rest.iter()
+        .rev()
+        .fold(*last, |bag, peak| hasher.hash_node(peak, &bag))
+}
+
+/// Recomputes the peak reached by `proof` from `leaf` in a range of
+/// `leaf_count` leaves, returning the peak's position and hash.
+pub fn recompute_mmr_peak<H: MerkleHasher>(
+    hasher: &H,
+    leaf_count: u32,
+    leaf: Hash,
+    proof: &MmrProof,
+) -> Result<(usize, Hash), ProgramError> {
+    let (position, height, start) =
+        locate_peak(leaf_count, proof.leaf_index).ok_or(MerkleTreeError::InvalidLeafIndex)?;
+    if proof.siblings.len() != height {
+        return Err(MerkleTreeError::InvalidProof.into());
+    }
+
+    let peak = recompute_merkle_root_from_leaf_hash(
+        hasher,
+        leaf,
+        proof.leaf_index - start,
+        &proof.siblings,
+    )?;
+    Ok((position, peak))
+}
+
+/// Upgrades `proof`, valid for a range of `leaf_count` leaves with the given
+/// `peaks`, to the range obtained by appending the `appended` leaf hashes.
+///
+/// The appends are replayed on a copy of `peaks`: whenever the proven leaf's
+/// peak is merged, the node it is merged with becomes its next sibling. Only
+/// the old peaks and the new leaves are needed, not the leaves of the range.
+pub fn upgrade_mmr_proof<H: MerkleHasher>(
+    hasher: &H,
+    proof: &MmrProof,
+    leaf_count: u32,
+    peaks: &[Hash],
+    appended: &[Hash],
+) -> Result<MmrProof, ProgramError> {
+    let (position, height, _) =
+        locate_peak(leaf_count, proof.leaf_index).ok_or(MerkleTreeError::InvalidLeafIndex)?;
+    if proof.siblings.len() != height || peaks.len() != leaf_count.count_ones() as usize {
+        return Err(MerkleTreeError::InvalidProof.into());
+    }
+
+    if appended.len() > (u32::MAX - leaf_count) as usize {
+        return Err(MerkleTreeError::TreeCapacityExceeded.into());
+    }
+
+    let mut peaks = peaks.to_vec();
+    let mut tracked = position;
+    let mut siblings = proof.siblings.clone();
+    for (leaf_count, leaf) in (leaf_count..=u32::MAX).zip(appended) {
+        // Same merges as `append_to_peaks`, noting the siblings of the leaf's
+        // peak once the carry has absorbed it.
+        let mut node = *leaf;
+        let mut carries_leaf = false;
+        for _ in 0..leaf_count.trailing_ones() {
+            let left = peaks.pop().ok_or(ProgramError::InvalidArgument)?;
+            if peaks.len() == tracked {
+                siblings.push(node);
+                carries_leaf = true;
+            } else if carries_leaf {
+                siblings.push(left);
+            }
+            node = hasher.hash_node(&left, &node);
+        }
+        peaks.push(node);
+        if carries_leaf {
+            tracked = peaks.len() - 1;
+        }
+    }
+
+    Ok(MmrProof {
+        leaf_index: proof.leaf_index,
+        siblings,
+    })
+}
+
+/// An in-memory Merkle mountain range for computing peaks and proofs
+/// off-chain, hashing like the on-chain program for the same `TreeConfig`.
+#[derive(Clone, Debug)]
+pub struct MerkleMountainRange<H: MerkleHasher = TreeConfig> {
+    hasher: H,
+    leaves: Vec<Hash>,
+    peaks: Vec<Hash>,
+}
+
+impl<H: MerkleHasher> MerkleMountainRange<H> {
+    /// Creates an empty range.
+    pub fn new(hasher: H) -> Self {
+        Self {
+            hasher,
+            leaves: Vec::new(),
+            peaks: Vec::new(),
+        }
+    }
+
+    /// Number of leaves in the range.
+    pub fn leaf_count(&self) -> u32 {
+        self.leaves.len() as u32
+    }
+
+    /// The leaf hashes, in append order.
+    pub fn leaves(&self) -> &[Hash] {
+        &self.leaves
+    }
+
+    /// The peaks, highest first.
+    pub fn peaks(&self) -> &[Hash] {
+        &self.peaks
+    }
+
+    /// The bagged peaks.
+    pub fn root(&self) -> Hash {
+        bag_peaks(&self.hasher, &self.peaks)
+    }
+
+    /// Hashes `leaf_data` and appends it.
+    pub fn append(&mut self, leaf_data: &[u8]) -> Result<(), ProgramError> {
+        let leaf = self.hasher.hash_leaf(leaf_data);
+        self.append_leaf_hash(leaf)
+    }
+
+    /// Appends a leaf hash as it is.
+    pub fn append_leaf_hash(&mut self, leaf: Hash) -> Result<(), ProgramError> {
+        let leaf_count = self.leaf_count();
+        append_to_peaks(&self.hasher, &mut self.peaks, leaf_count, leaf)?;
+        self.leaves.push(leaf);
+        Ok(())
+    }
+
+    /// Builds the proof of the leaf at `leaf_index` against the current peaks.
+    pub fn proof(&self, leaf_index: u32) -> Result<MmrProof, ProgramError> {
+        let (_, height, start) =
+            locate_peak(self.leaf_count(), leaf_index).ok_or(MerkleTreeError::InvalidLeafIndex)?;
+        let start = start as usize;
+        let peak_leaves = &self.leaves[start..start + (1 << height)];
+        Ok(MmrProof {
+            leaf_index,
+            siblings: build_proof(&self.hasher, leaf_index as usize - start, peak_leaves)?,
+        })
+    }
+
+    /// Checks `proof` for `leaf` against the current peaks.
+    pub fn verify(&self, leaf: Hash, proof: &MmrProof) -> bool {
+        recompute_mmr_peak(&self.hasher, self.leaf_count(), leaf, proof)
+            .is_ok_and(|(position, peak)| self.peaks[position] == peak)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn range(config: TreeConfig, leaf_count: usize) -> MerkleMountainRange {
+        let mut range = MerkleMountainRange::new(config);
+        for i in 0..leaf_count {
+            range.append(format!("leaf {i}").as_bytes()).unwrap();
+        }
+        range
+    }
+
+    #[test]
+    fn proof_of_a_lone_peak_survives_its_merge() {
+        let config = TreeConfig::default();
+        let old = range(config, 3);
+        let proof = old.proof(2).unwrap();
+        assert!(proof.siblings.is_empty());
+
+        // The fourth leaf merges every peak into one of height 2.
+        let new = range(config, 4);
+        assert!(!new.verify(new.leaves()[2], &proof));
+        let upgraded =
+            upgrade_mmr_proof(&config, &proof, 3, old.peaks(), &new.leaves()[3..]).unwrap();
+        assert_eq!(upgraded, new.proof(2).unwrap());
+        assert!(new.verify(new.leaves()[2], &upgraded));
+    }
+
+    #[test]
+    fn upgraded_proofs_match_fresh_ones() {
+        let config = TreeConfig {
+            domain_separated: true,
+            ..TreeConfig::default()
+        };
+        let ranges = (0..=20).map(|n| range(config, n)).collect::<Vec<_>>();
+        let latest = &ranges[20];
+
+        for old in &ranges[1..] {
+            for leaf_index in 0..old.leaf_count() {
+                let proof = old.proof(leaf_index).unwrap();
+                for new in &ranges[old.leaf_count() as usize..] {
+                    let appended = &latest.leaves()[old.leaves().len()..new.leaves().len()];
+                    let upgraded =
+                        upgrade_mmr_proof(&config, &proof, old.leaf_count(), old.peaks(), appended)
+                            .unwrap();
+                    assert_eq!(upgraded, new.proof(leaf_index).unwrap());
+                    assert!(upgraded.siblings.starts_with(&proof.siblings));
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,228 @@
+//! Sparse Merkle trees over a 256-bit key space.
+//!
+//! Every possible key has a leaf slot at depth 256. A slot holds the hash of
+//! its key and value, or the empty node when the key is absent, so absence can
+//! be proven just like membership.
+
+use std::collections::BTreeMap;
+
+use borsh::{BorshDeserialize, BorshSerialize};
+use solana_program::{hash::Hash, program_error::ProgramError};
+
+use crate::{
+    errors::MerkleTreeError,
+    state::{NodeHashing, TreeConfig},
+    utils::{empty_subtree_hashes, MerkleHasher},
+};
+
+/// Number of levels above the leaves of a sparse tree.
+pub const SPARSE_TREE_DEPTH: usize = 256;
+
+/// A key of a sparse tree. Bit `h`, counting from the least significant bit of
+/// the big-endian number, is the path direction at height `h`.
+pub type SparseKey = [u8; 32];
+
+/// A compressed proof for one leaf slot of a sparse tree.
+#[derive(BorshSerialize, BorshDeserialize, Clone, Debug, Default, PartialEq)]
+pub struct SparseMerkleProof {
+    /// Bit `h` (numbered like key bits) is set when the sibling at height `h`
+    /// is not the root of an empty subtree and is therefore listed.
+    pub bitmap: [u8; 32],
+
+    /// Siblings that are not empty subtrees, from the leaf level up.
+    pub siblings: Vec<Hash>,
+}
+
+/// Returns bit `height` of `bits`, numbered from the least significant bit.
+fn bit(bits: &[u8; 32], height: usize) -> bool {
+    bits[31 - height / 8] & (1 << (height % 8)) != 0
+}
+
+/// Flips bit `height` of `bits`.
+fn flip_bit(bits: &mut [u8; 32], height: usize) {
+    bits[31 - height / 8] ^= 1 << (height % 8);
+}
+
+/// Position of the ancestor of `key`'s leaf at `height`: the key with its
+/// `height` lowest bits cleared.
+fn ancestor(key: &SparseKey, height: usize) -> SparseKey {
+    let mut position = *key;
+    for h in 0..height {
+        position[31 - h / 8] &= !(1 << (h % 8));
+    }
+    position
+}
+
+/// Hash of the leaf holding `value` under `key`. The key is part of the leaf so
+/// a value cannot be proven under another key.
+pub fn sparse_leaf_hash<H: MerkleHasher>(hasher: &H, key: &SparseKey, value: &[u8]) -> Hash {
+    let mut leaf_data = Vec::with_capacity(key.len() + value.len());
+    leaf_data.extend_from_slice(key);
+    leaf_data.extend_from_slice(value);
+    hasher.hash_leaf(&leaf_data)
+}
+
+/// Recomputes the root of a sparse tree from the `leaf` in `key`'s slot, which
+/// is `empty_hashes[0]` for an absent key, and its proof.
+///
+/// `empty_hashes` must come from `empty_subtree_hashes` with the same hasher
+/// and `SPARSE_TREE_DEPTH`.
+pub fn recompute_sparse_root<H: MerkleHasher>(
+    hasher: &H,
+    empty_hashes: &[Hash],
+    key: &SparseKey,
+    leaf: Hash,
+    proof: &SparseMerkleProof,
+) -> Result<Hash, ProgramError> {
+    let mut siblings = proof.siblings.iter();
+    let mut current = leaf;
+
+    for height in 0..SPARSE_TREE_DEPTH {
+        let empty = empty_hashes[height];
+        let sibling = if bit(&proof.bitmap, height) {
+            *siblings.next().ok_or(MerkleTreeError::InvalidProof)?
+        } else {
+            empty
+        };
+
+        // Two empty subtrees make an empty subtree; skip hashing them again.
+        current = if current == empty && sibling == empty {
+            empty_hashes[height + 1]
+        } else if bit(key, height) {
+            hasher.hash_node(&sibling, &current)
+        } else {
+            hasher.hash_node(&current, &sibling)
+        };
+    }
+
+    // Leftover siblings mean the bitmap does not describe the proof.
+    if siblings.next().is_some() {
+        return Err(MerkleTreeError::InvalidProof.into());
+    }
+
+    Ok(current)
+}
+
+/// Rejects configurations a sparse tree cannot be built with.
+///
+/// With `NodeHashing::SortedPair` the root does not depend on the path, so a
+/// proof that one slot is empty would prove every key absent.
+pub fn check_sparse_config(config: &TreeConfig) -> Result<(), ProgramError> {
+    match config.node_hashing {
+        NodeHashing::Positional => Ok(()),
+        NodeHashing::SortedPair => Err(MerkleTreeError::InvalidInstructionData.into()),
+    }
+}
+
+/// An in-memory sparse Merkle tree for computing roots and proofs off-chain,
+/// hashing like the on-chain program for the same `TreeConfig`.
+#[derive(Clone, Debug)]
+pub struct SparseMerkleTree<H: MerkleHasher = TreeConfig> {
+    hasher: H,
+    empty_hashes: Vec<Hash>,
+
+    /// Nodes that are not empty subtrees, by height and position.
+    nodes: BTreeMap<(usize, SparseKey), Hash>,
+}
+
+impl<H: MerkleHasher> SparseMerkleTree<H> {
+    /// Creates an empty tree.
+    pub fn new(hasher: H) -> Self {
+        let empty_hashes = empty_subtree_hashes(&hasher, SPARSE_TREE_DEPTH);
+        Self {
+            hasher,
+            empty_hashes,
+            nodes: BTreeMap::new(),
+        }
+    }
+
+    /// The Merkle root.
+    pub fn root(&self) -> Hash {
+        self.node(SPARSE_TREE_DEPTH, &[0; 32])
+    }
+
+    /// Number of keys in the tree.
+    pub fn len(&self) -> usize {
+        self.nodes.range((0, [0; 32])..(1, [0; 32])).count()
+    }
+
+    /// Whether the tree holds no keys.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Whether `key` is in the tree.
+    pub fn contains(&self, key: &SparseKey) -> bool {
+        self.nodes.contains_key(&(0, *key))
+    }
+
+    /// Sets the value stored under `key`.
+    pub fn insert(&mut self, key: &SparseKey, value: &[u8]) {
+        let leaf = sparse_leaf_hash(&self.hasher, key, value);
+        self.set_leaf(key, leaf);
+    }
+
+    /// Removes `key`, returning whether it was present.
+    pub fn remove(&mut self, key: &SparseKey) -> bool {
+        let present = self.contains(key);
+        self.set_leaf(key, self.empty_hashes[0]);
+        present
+    }
+
+    /// Builds the proof for `key`'s slot, whether or not the key is present.
+    pub fn proof(&self, key: &SparseKey) -> SparseMerkleProof {
+        let mut proof = SparseMerkleProof::default();
+        for height in 0..SPARSE_TREE_DEPTH {
+            let mut sibling_position = ancestor(key, height);
+            flip_bit(&mut sibling_position, height);
+            if let Some(sibling) = self.nodes.get(&(height, sibling_position)) {
+                flip_bit(&mut proof.bitmap, height);
+                proof.siblings.push(*sibling);
+            }
+        }
+        proof
+    }
+
+    /// Checks `proof` against this tree's root: that `key` holds `value`, or
+    /// that `key` is absent when `value` is `None`.
+    pub fn verify(&self, key: &SparseKey, value: Option<&[u8]>, proof: &SparseMerkleProof) -> bool {
+        let leaf = match value {
+            Some(value) => sparse_leaf_hash(&self.hasher, key, value),
+            None => self.empty_hashes[0],
+        };
+        recompute_sparse_root(&self.hasher, &self.empty_hashes, key, leaf, proof)
+            .is_ok_and(|root| root == self.root())
+    }
+
+    fn node(&self, height: usize, position: &SparseKey) -> Hash {
+        self.nodes
+            .get(&(height, *position))
+            .copied()
+            .unwrap_or(self.empty_hashes[height])
+    }
+
+    /// Stores `leaf` in `key`'s slot and updates the nodes along its path.
+    fn set_leaf(&mut self, key: &SparseKey, leaf: Hash) {
+        let mut current = leaf;
+        for height in 0..=SPARSE_TREE_DEPTH {
+            let position = ancestor(key, height);
+            if current == self.empty_hashes[height] {
+                self.nodes.remove(&(height, position));
+            } else {
+                self.nodes.insert((height, position), current);
+            }
+            if height == SPARSE_TREE_DEPTH {
+                break;
+            }
+
+            let mut sibling_position = position;
+            flip_bit(&mut sibling_position, height);
+            let sibling = self.node(height, &sibling_position);
+            current = if bit(key, height) {
+                self.hasher.hash_node(&sibling, &current)
+            } else {
+                self.hasher.hash_node(&current, &sibling)
+            };
+        }
+    }
+}
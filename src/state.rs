@@ -1,110 +1,1587 @@
+use borsh::{BorshDeserialize, BorshSerialize};
 use solana_program::{
-    hash::Hash,
-    program_error::ProgramError,
-    program_pack::{IsInitialized, Pack, Sealed},
+    hash::Hash, program_error::ProgramError, program_pack::IsInitialized, pubkey::Pubkey,
+    rent::Rent,
 };
 
-/// Total size allocated for the account.
-const MERKLE_TREE_ACCOUNT_LEN: usize = 1024;
+use crate::{
+    errors::MerkleTreeError,
+    mmr::{append_to_peaks, bag_peaks, MAX_MMR_PEAKS},
+    utils::{empty_subtree_hashes, tree_depth, MerkleHasher},
+};
 
-/// Number of bytes to store the `is_initialized` flag (1 byte).
-const IS_INITIALIZED_SIZE: usize = 1;
+/// Number of bytes to store the account kind, which doubles as the
+/// `is_initialized` flag (1 byte).
+const ACCOUNT_KIND_SIZE: usize = 1;
 
 /// Size of a Solana `Hash` (32 bytes).
 const HASH_SIZE: usize = 32;
 
-/// Offset at which the Merkle root starts (immediately after the init flag).
-const ROOT_START: usize = IS_INITIALIZED_SIZE;
+/// Layout version written by this program.
+pub const CURRENT_ACCOUNT_VERSION: u8 = 4;
+
+/// Sparse tree layout version written by this program.
+pub const CURRENT_SPARSE_ACCOUNT_VERSION: u8 = 1;
+
+/// Concurrent tree layout version written by this program.
+pub const CURRENT_CONCURRENT_ACCOUNT_VERSION: u8 = 1;
+
+/// Incremental tree layout version written by this program.
+pub const CURRENT_INCREMENTAL_ACCOUNT_VERSION: u8 = 1;
+
+/// Merkle mountain range layout version written by this program.
+pub const CURRENT_MMR_ACCOUNT_VERSION: u8 = 1;
+
+/// Offset of the layout version (immediately after the account kind).
+const VERSION_START: usize = ACCOUNT_KIND_SIZE;
+
+/// Offset at which the tree configuration starts (immediately after the version).
+const TREE_CONFIG_START: usize = VERSION_START + 1;
+
+/// Offset of the pre-hashed leaves flag (immediately after the tree configuration).
+const LEAVES_PREHASHED_START: usize = TREE_CONFIG_START + TreeConfig::LEN;
+
+/// Offset of the storage mode (immediately after the pre-hashed flag).
+const STORAGE_START: usize = LEAVES_PREHASHED_START + 1;
+
+/// Offset of the leaf count (`u32`, little endian).
+const LEAF_COUNT_START: usize = STORAGE_START + 1;
+
+/// Offset of the tree depth (`u8`).
+const DEPTH_START: usize = LEAF_COUNT_START + 4;
+
+/// Offset at which the authority starts.
+const AUTHORITY_START: usize = DEPTH_START + 1;
+
+/// Offset at which the Merkle root starts (immediately after the authority).
+const ROOT_START: usize = AUTHORITY_START + 32;
 
 /// Offset at which the Merkle root ends.
 const ROOT_END: usize = ROOT_START + HASH_SIZE;
 
+/// Offset of the number of root history slots (`u8`).
+const ROOT_HISTORY_SIZE_START: usize = ROOT_END;
+
+/// Offset of the number of filled root history slots (`u8`).
+const ROOT_HISTORY_LEN_START: usize = ROOT_HISTORY_SIZE_START + 1;
+
+/// Offset at which the root history begins; the leaves follow it.
+const ROOT_HISTORY_START: usize = ROOT_HISTORY_LEN_START + 1;
+
+/// Size of a single root history slot: a root and its leaf count.
+const HISTORIC_ROOT_SIZE: usize = HASH_SIZE + 4;
+
+/// Size of a single stored leaf.
+pub const LEAF_SIZE: usize = HASH_SIZE;
+
+/// Offset of a sparse tree's entry count (`u64`, little endian), immediately
+/// after its configuration.
+const SPARSE_ENTRY_COUNT_START: usize = TREE_CONFIG_START + TreeConfig::LEN;
+
+/// Offset at which a sparse tree's authority starts.
+const SPARSE_AUTHORITY_START: usize = SPARSE_ENTRY_COUNT_START + 8;
+
+/// Offset at which a sparse tree's root starts (immediately after the authority).
+const SPARSE_ROOT_START: usize = SPARSE_AUTHORITY_START + 32;
+
+/// Offset at which a sparse tree's root ends, which is also its account size.
+const SPARSE_ROOT_END: usize = SPARSE_ROOT_START + HASH_SIZE;
+
+/// Offset of a concurrent tree's maximum depth (`u8`), immediately after its
+/// configuration.
+const CONCURRENT_MAX_DEPTH_START: usize = TREE_CONFIG_START + TreeConfig::LEN;
+
+/// Offset of a concurrent tree's changelog capacity (`u32`, little endian).
+const CONCURRENT_MAX_BUFFER_SIZE_START: usize = CONCURRENT_MAX_DEPTH_START + 1;
+
+/// Offset at which a concurrent tree's authority starts.
+const CONCURRENT_AUTHORITY_START: usize = CONCURRENT_MAX_BUFFER_SIZE_START + 4;
+
+/// Offset of a concurrent tree's sequence number (`u64`, little endian).
+const CONCURRENT_SEQUENCE_NUMBER_START: usize = CONCURRENT_AUTHORITY_START + 32;
+
+/// Offset of the changelog slot holding the latest change (`u32`, little endian).
+const CONCURRENT_ACTIVE_INDEX_START: usize = CONCURRENT_SEQUENCE_NUMBER_START + 8;
+
+/// Offset of the number of filled changelog slots (`u32`, little endian).
+const CONCURRENT_BUFFER_SIZE_START: usize = CONCURRENT_ACTIVE_INDEX_START + 4;
+
+/// Offset of the number of appended leaves (`u32`, little endian).
+const CONCURRENT_RIGHTMOST_INDEX_START: usize = CONCURRENT_BUFFER_SIZE_START + 4;
+
+/// Offset at which the last appended leaf starts.
+const CONCURRENT_RIGHTMOST_LEAF_START: usize = CONCURRENT_RIGHTMOST_INDEX_START + 4;
+
+/// Offset at which a concurrent tree's root starts.
+const CONCURRENT_ROOT_START: usize = CONCURRENT_RIGHTMOST_LEAF_START + HASH_SIZE;
+
+/// Offset at which a concurrent tree's root ends, and the proof of the last
+/// appended leaf begins. The changelog buffer follows that proof.
+const CONCURRENT_ROOT_END: usize = CONCURRENT_ROOT_START + HASH_SIZE;
+
+/// Deepest concurrent tree supported: `rightmost_index` counts the appended
+/// leaves in a `u32`, which must reach `2^max_depth` once the tree is full.
+pub const MAX_CONCURRENT_TREE_DEPTH: u8 = 31;
+
+/// Offset of an incremental tree's depth (`u8`), immediately after its
+/// configuration.
+const INCREMENTAL_DEPTH_START: usize = TREE_CONFIG_START + TreeConfig::LEN;
+
+/// Offset at which an incremental tree's authority starts.
+const INCREMENTAL_AUTHORITY_START: usize = INCREMENTAL_DEPTH_START + 1;
+
+/// Offset of an incremental tree's leaf count (`u64`, little endian).
+const INCREMENTAL_LEAF_COUNT_START: usize = INCREMENTAL_AUTHORITY_START + 32;
+
+/// Offset at which an incremental tree's root starts.
+const INCREMENTAL_ROOT_START: usize = INCREMENTAL_LEAF_COUNT_START + 8;
+
+/// Offset at which an incremental tree's root ends, and its filled subtrees
+/// begin.
+const INCREMENTAL_ROOT_END: usize = INCREMENTAL_ROOT_START + HASH_SIZE;
+
+/// Deepest incremental tree supported, so leaf indices fit in a `u32`.
+pub const MAX_INCREMENTAL_TREE_DEPTH: u8 = 32;
+
+/// Offset at which a Merkle mountain range's authority starts, immediately
+/// after its configuration.
+const MMR_AUTHORITY_START: usize = TREE_CONFIG_START + TreeConfig::LEN;
+
+/// Offset of a Merkle mountain range's leaf count (`u32`, little endian).
+const MMR_LEAF_COUNT_START: usize = MMR_AUTHORITY_START + 32;
+
+/// Offset at which a Merkle mountain range's bagged root starts.
+const MMR_ROOT_START: usize = MMR_LEAF_COUNT_START + 4;
+
+/// Offset at which a Merkle mountain range's root ends, and its peak slots
+/// begin.
+const MMR_ROOT_END: usize = MMR_ROOT_START + HASH_SIZE;
+
+/// Offset at which a Merkle mountain range's peak slots end, which is also its
+/// account size.
+const MMR_PEAKS_END: usize = MMR_ROOT_END + MAX_MMR_PEAKS * HASH_SIZE;
+
+/// Kind of a program account, stored in its first byte.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(u8)]
+pub enum AccountKind {
+    /// Not initialized yet.
+    Uninitialized = 0,
+
+    /// A `MerkleTreeAccount`.
+    MerkleTree = 1,
+
+    /// A `SparseMerkleTreeAccount`.
+    SparseMerkleTree = 2,
+
+    /// A `ConcurrentMerkleTreeAccount`.
+    ConcurrentMerkleTree = 3,
+
+    /// An `IncrementalMerkleTreeAccount`.
+    IncrementalMerkleTree = 4,
+
+    /// A `MerkleMountainRangeAccount`.
+    MerkleMountainRange = 5,
+}
+
+impl TryFrom<u8> for AccountKind {
+    type Error = ProgramError;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            0 => Ok(AccountKind::Uninitialized),
+            1 => Ok(AccountKind::MerkleTree),
+            2 => Ok(AccountKind::SparseMerkleTree),
+            3 => Ok(AccountKind::ConcurrentMerkleTree),
+            4 => Ok(AccountKind::IncrementalMerkleTree),
+            5 => Ok(AccountKind::MerkleMountainRange),
+            _ => Err(ProgramError::InvalidAccountData),
+        }
+    }
+}
+
+/// Reads the kind of the account in `src`, failing if it is initialized as
+/// anything other than `expected`.
+fn check_account_kind(src: &[u8], expected: AccountKind) -> Result<AccountKind, ProgramError> {
+    let kind = AccountKind::try_from(*src.first().ok_or(ProgramError::InvalidAccountData)?)?;
+    if kind != AccountKind::Uninitialized && kind != expected {
+        return Err(MerkleTreeError::AccountKindMismatch.into());
+    }
+    Ok(kind)
+}
+
+/// Hash function used for leaves and nodes.
+#[derive(BorshSerialize, BorshDeserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[borsh(use_discriminant = true)]
+#[repr(u8)]
+pub enum HashFunction {
+    /// SHA-256, the Solana-native `hash` syscall.
+    #[default]
+    Sha256 = 0,
+
+    /// Keccak-256, as used by the EVM.
+    Keccak256 = 1,
+}
+
+impl TryFrom<u8> for HashFunction {
+    type Error = ProgramError;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            0 => Ok(HashFunction::Sha256),
+            1 => Ok(HashFunction::Keccak256),
+            _ => Err(ProgramError::InvalidArgument),
+        }
+    }
+}
+
+/// How two child nodes are combined into their parent.
+#[derive(BorshSerialize, BorshDeserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[borsh(use_discriminant = true)]
+#[repr(u8)]
+pub enum NodeHashing {
+    /// `hash(left || right)`, keeping the children in tree order.
+    #[default]
+    Positional = 0,
+
+    /// `hash(min || max)`, ordering the children by value (OpenZeppelin-style).
+    SortedPair = 1,
+}
+
+impl TryFrom<u8> for NodeHashing {
+    type Error = ProgramError;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            0 => Ok(NodeHashing::Positional),
+            1 => Ok(NodeHashing::SortedPair),
+            _ => Err(ProgramError::InvalidArgument),
+        }
+    }
+}
+
+/// Hashing parameters chosen when the tree is built.
+///
+/// `Keccak256` combined with `SortedPair` and no domain separation produces
+/// the same nodes as OpenZeppelin's `MerkleProof.verify`, so proofs for such a
+/// tree can be checked on the EVM as well.
+///
+/// `Keccak256` trees with `SortedPair` hashing also use the complete binary
+/// tree layout of OpenZeppelin's JS `merkle-tree` library (see
+/// `TreeLayout::Complete`), so their roots and proofs match the library's for
+/// any leaf count, as long as the leaves are in the same order
+/// (`sortLeaves: false`, or sorted before building). Other trees pair the last
+/// node of an odd level with itself.
+#[derive(BorshSerialize, BorshDeserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct TreeConfig {
+    /// Hash function used for leaves and nodes.
+    pub hash_function: HashFunction,
+
+    /// Scheme used to combine child nodes.
+    pub node_hashing: NodeHashing,
+
+    /// Whether leaves and nodes are hashed with distinct prefixes
+    /// (`0x00` for leaves, `0x01` for nodes, as in RFC 6962), so that a leaf
+    /// can never be mistaken for an interior node.
+    pub domain_separated: bool,
+}
+
+impl TreeConfig {
+    /// Serialized size of the configuration (hash function, node hashing,
+    /// domain separation).
+    pub const LEN: usize = 3;
+
+    /// Writes the configuration into the first `TreeConfig::LEN` bytes of `dst`.
+    pub fn pack_into_slice(&self, dst: &mut [u8]) {
+        dst[0] = self.hash_function as u8;
+        dst[1] = self.node_hashing as u8;
+        dst[2] = self.domain_separated as u8;
+    }
+
+    /// Reads a configuration from the first `TreeConfig::LEN` bytes of `src`.
+    pub fn unpack_from_slice(src: &[u8]) -> Result<Self, ProgramError> {
+        let src = src.get(..Self::LEN).ok_or(ProgramError::InvalidArgument)?;
+
+        let hash_function = HashFunction::try_from(src[0])?;
+        let node_hashing = NodeHashing::try_from(src[1])?;
+        let domain_separated = match src[2] {
+            0 => false,
+            1 => true,
+            _ => return Err(ProgramError::InvalidArgument),
+        };
+
+        Ok(TreeConfig {
+            hash_function,
+            node_hashing,
+            domain_separated,
+        })
+    }
+}
+
+/// What a tree account keeps besides its header and root.
+#[derive(BorshSerialize, BorshDeserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[borsh(use_discriminant = true)]
+#[repr(u8)]
+pub enum TreeStorage {
+    /// Every leaf hash is stored, so proofs can be built on-chain.
+    #[default]
+    Leaves = 0,
+
+    /// Only the root and leaf count are stored; proofs are built off-chain
+    /// and rent stays constant whatever the number of leaves.
+    RootOnly = 1,
+}
+
+impl TryFrom<u8> for TreeStorage {
+    type Error = ProgramError;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            0 => Ok(TreeStorage::Leaves),
+            1 => Ok(TreeStorage::RootOnly),
+            _ => Err(ProgramError::InvalidArgument),
+        }
+    }
+}
+
+/// A root the tree had before its latest changes.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct HistoricRoot {
+    /// The root.
+    pub root: Hash,
+
+    /// Number of leaves the tree had with that root.
+    pub leaf_count: u32,
+}
+
 /// Represents the state of the Merkle tree.
 #[derive(Clone, Debug, Default, PartialEq)]
 pub struct MerkleTreeAccount {
     /// Whether the tree is initialized.
     pub is_initialized: bool,
 
+    /// Layout version of the account data.
+    pub version: u8,
+
+    /// Hashing parameters the tree was built with.
+    pub config: TreeConfig,
+
+    /// Whether the leaves were supplied as hashes rather than hashed by the
+    /// program from raw data.
+    pub leaves_prehashed: bool,
+
+    /// Whether the leaves are kept in the account.
+    pub storage: TreeStorage,
+
+    /// Number of leaves in the tree.
+    pub leaf_count: u32,
+
+    /// Number of levels above the leaves, i.e. the length of a proof.
+    pub depth: u8,
+
+    /// Key allowed to mutate the tree.
+    pub authority: Pubkey,
+
     /// The Merkle root (Solana `Hash`).
     pub root: Hash,
 
-    /// Flattened list of leaf node hashes (`Hash`).
+    /// Number of previous roots kept in `root_history`.
+    pub root_history_size: u8,
+
+    /// Previous roots, newest first, so proofs built shortly before a change
+    /// still verify.
+    pub root_history: Vec<HistoricRoot>,
+
+    /// Flattened list of leaf node hashes (`Hash`); empty for root-only trees.
     pub leaves: Vec<Hash>,
 }
 
-impl Sealed for MerkleTreeAccount {}
-
 impl IsInitialized for MerkleTreeAccount {
     fn is_initialized(&self) -> bool {
         self.is_initialized
     }
 }
 
-impl Pack for MerkleTreeAccount {
-    /// The total space this account must at least occupy.
-    const LEN: usize = MERKLE_TREE_ACCOUNT_LEN;
+impl MerkleTreeAccount {
+    /// Size of the fixed header preceding the root history and the leaves.
+    pub const HEADER_LEN: usize = ROOT_HISTORY_START;
 
-    fn pack_into_slice(&self, dst: &mut [u8]) {
-        // 1) Write initialization flag (1 byte).
-        dst[0] = self.is_initialized as u8;
+    /// Account space needed to store a tree of up to `capacity` leaves that
+    /// keeps `root_history_size` previous roots.
+    pub fn space_for(capacity: usize, root_history_size: u8) -> usize {
+        Self::leaves_start(root_history_size) + capacity * LEAF_SIZE
+    }
+
+    /// Lamports needed to keep such a tree rent exempt.
+    pub fn minimum_balance(rent: &Rent, capacity: usize, root_history_size: u8) -> u64 {
+        rent.minimum_balance(Self::space_for(capacity, root_history_size))
+    }
+
+    /// Number of leaves an account with `data_len` bytes can hold when it
+    /// keeps `root_history_size` previous roots.
+    pub fn capacity(data_len: usize, root_history_size: u8) -> usize {
+        data_len.saturating_sub(Self::leaves_start(root_history_size)) / LEAF_SIZE
+    }
+
+    /// Offset at which the leaves begin.
+    fn leaves_start(root_history_size: u8) -> usize {
+        ROOT_HISTORY_START + root_history_size as usize * HISTORIC_ROOT_SIZE
+    }
+
+    /// Makes `root` the root of a tree of `leaf_count` leaves, moving the
+    /// current root into the history.
+    pub fn set_root(&mut self, root: Hash, leaf_count: u32) {
+        if self.root_history_size > 0 {
+            let previous = HistoricRoot {
+                root: self.root,
+                leaf_count: self.leaf_count,
+            };
+            self.root_history.insert(0, previous);
+            self.root_history.truncate(self.root_history_size as usize);
+        }
+        self.root = root;
+        self.leaf_count = leaf_count;
+        self.depth = tree_depth(leaf_count as usize);
+    }
+
+    /// The current root followed by the historic ones, newest first.
+    pub fn known_roots(&self) -> impl Iterator<Item = HistoricRoot> + '_ {
+        let current = HistoricRoot {
+            root: self.root,
+            leaf_count: self.leaf_count,
+        };
+        std::iter::once(current).chain(self.root_history.iter().copied())
+    }
+
+    /// Unpacks an initialized tree account.
+    pub fn unpack(src: &[u8]) -> Result<Self, ProgramError> {
+        let account = Self::unpack_unchecked(src)?;
+        if !account.is_initialized {
+            return Err(ProgramError::UninitializedAccount);
+        }
+        Ok(account)
+    }
+
+    /// Packs `src` into `dst`, failing if its leaves exceed the account's capacity.
+    pub fn pack(src: Self, dst: &mut [u8]) -> Result<(), ProgramError> {
+        src.pack_into_slice(dst)
+    }
+
+    fn pack_into_slice(&self, dst: &mut [u8]) -> Result<(), ProgramError> {
+        if dst.len() < Self::leaves_start(self.root_history_size) {
+            return Err(ProgramError::AccountDataTooSmall);
+        }
+        if self.root_history.len() > self.root_history_size as usize {
+            return Err(ProgramError::InvalidAccountData);
+        }
+        if self.leaves.len() > Self::capacity(dst.len(), self.root_history_size) {
+            return Err(MerkleTreeError::TreeCapacityExceeded.into());
+        }
+
+        // 1) Write the header: init flag, version, configuration, pre-hashed
+        //    flag, storage mode, leaf count, depth and authority.
+        dst[0] = if self.is_initialized {
+            AccountKind::MerkleTree
+        } else {
+            AccountKind::Uninitialized
+        } as u8;
+        dst[VERSION_START] = self.version;
+        self.config
+            .pack_into_slice(&mut dst[TREE_CONFIG_START..LEAVES_PREHASHED_START]);
+        dst[LEAVES_PREHASHED_START] = self.leaves_prehashed as u8;
+        dst[STORAGE_START] = self.storage as u8;
+        dst[LEAF_COUNT_START..DEPTH_START].copy_from_slice(&self.leaf_count.to_le_bytes());
+        dst[DEPTH_START] = self.depth;
+        dst[AUTHORITY_START..ROOT_START].copy_from_slice(self.authority.as_ref());
 
         // 2) Write Merkle root (32 bytes).
         let root_bytes = self.root.to_bytes();
         dst[ROOT_START..ROOT_END].copy_from_slice(&root_bytes);
 
-        // 3) Write leaves (32 bytes per leaf).
-        let mut offset = ROOT_END;
+        // 3) Write the root history, newest first, into its fixed slots.
+        dst[ROOT_HISTORY_SIZE_START] = self.root_history_size;
+        dst[ROOT_HISTORY_LEN_START] = self.root_history.len() as u8;
+        let mut offset = ROOT_HISTORY_START;
+        for historic in &self.root_history {
+            dst[offset..offset + HASH_SIZE].copy_from_slice(historic.root.as_ref());
+            dst[offset + HASH_SIZE..offset + HISTORIC_ROOT_SIZE]
+                .copy_from_slice(&historic.leaf_count.to_le_bytes());
+            offset += HISTORIC_ROOT_SIZE;
+        }
+
+        // 4) Write leaves (32 bytes per leaf); capacity was checked above.
+        let mut offset = Self::leaves_start(self.root_history_size);
         for leaf in &self.leaves {
-            let leaf_bytes = leaf.to_bytes();
-            let leaf_end = offset + HASH_SIZE;
+            let leaf_end = offset + LEAF_SIZE;
+            dst[offset..leaf_end].copy_from_slice(leaf.as_ref());
+            offset = leaf_end;
+        }
 
-            // Safety check: ensure we don't overrun `dst`.
-            if leaf_end > dst.len() {
-                break; // or return an error if you prefer
-            }
-            dst[offset..leaf_end].copy_from_slice(&leaf_bytes);
+        Ok(())
+    }
+
+    /// Unpacks a tree account, returning a default value if it is not yet initialized.
+    pub fn unpack_unchecked(src: &[u8]) -> Result<Self, ProgramError> {
+        // 1) Read the account kind, which tells whether it is initialized.
+        let is_initialized =
+            check_account_kind(src, AccountKind::MerkleTree)? == AccountKind::MerkleTree;
+        if !is_initialized {
+            return Ok(MerkleTreeAccount::default());
+        }
+
+        // 2) Read the version, rejecting layouts this program does not know.
+        let version = *src
+            .get(VERSION_START)
+            .ok_or(ProgramError::InvalidAccountData)?;
+        if version != CURRENT_ACCOUNT_VERSION {
+            return Err(MerkleTreeError::UnsupportedAccountVersion.into());
+        }
+
+        // 3) Read the rest of the header.
+        let header = src
+            .get(..Self::HEADER_LEN)
+            .ok_or(ProgramError::InvalidAccountData)?;
+        let config =
+            TreeConfig::unpack_from_slice(&header[TREE_CONFIG_START..LEAVES_PREHASHED_START])
+                .map_err(|_| ProgramError::InvalidAccountData)?;
+        let leaves_prehashed = match header[LEAVES_PREHASHED_START] {
+            0 => false,
+            1 => true,
+            _ => return Err(ProgramError::InvalidAccountData),
+        };
+        let storage = TreeStorage::try_from(header[STORAGE_START])
+            .map_err(|_| ProgramError::InvalidAccountData)?;
+        let leaf_count = u32::from_le_bytes(
+            header[LEAF_COUNT_START..DEPTH_START]
+                .try_into()
+                .map_err(|_| ProgramError::InvalidAccountData)?,
+        );
+        let depth = header[DEPTH_START];
+        let authority = Pubkey::new_from_array(
+            header[AUTHORITY_START..ROOT_START]
+                .try_into()
+                .map_err(|_| ProgramError::InvalidAccountData)?,
+        );
+
+        // 4) Read Merkle root (32 bytes).
+        let root = Hash::new(&header[ROOT_START..ROOT_END]);
+
+        // 5) Read the filled root history slots.
+        let root_history_size = header[ROOT_HISTORY_SIZE_START];
+        let root_history_len = header[ROOT_HISTORY_LEN_START];
+        if root_history_len > root_history_size {
+            return Err(ProgramError::InvalidAccountData);
+        }
+        let root_history = src
+            .get(ROOT_HISTORY_START..Self::leaves_start(root_history_len))
+            .ok_or(ProgramError::InvalidAccountData)?
+            .chunks_exact(HISTORIC_ROOT_SIZE)
+            .map(|slot| HistoricRoot {
+                root: Hash::new(&slot[..HASH_SIZE]),
+                leaf_count: u32::from_le_bytes([slot[32], slot[33], slot[34], slot[35]]),
+            })
+            .collect();
+
+        // 6) Read exactly `leaf_count` leaves (32 bytes each), if stored.
+        let leaves_start = Self::leaves_start(root_history_size);
+        let stored_leaves = match storage {
+            TreeStorage::Leaves => leaf_count as usize,
+            TreeStorage::RootOnly => 0,
+        };
+        let leaves_end = stored_leaves
+            .checked_mul(LEAF_SIZE)
+            .and_then(|len| len.checked_add(leaves_start))
+            .ok_or(ProgramError::InvalidAccountData)?;
+        let leaves = src
+            .get(leaves_start..leaves_end)
+            .ok_or(ProgramError::InvalidAccountData)?
+            .chunks_exact(LEAF_SIZE)
+            .map(Hash::new)
+            .collect();
+
+        Ok(MerkleTreeAccount {
+            is_initialized,
+            version,
+            config,
+            leaves_prehashed,
+            storage,
+            leaf_count,
+            depth,
+            authority,
+            root,
+            root_history_size,
+            root_history,
+            leaves,
+        })
+    }
+}
+
+/// State of a sparse Merkle tree. Only the root is stored; see `crate::sparse`.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SparseMerkleTreeAccount {
+    /// Whether the account has been initialized.
+    pub is_initialized: bool,
+
+    /// Layout version the account was written with.
+    pub version: u8,
+
+    /// Hashing scheme used for leaves and nodes.
+    pub config: TreeConfig,
+
+    /// Number of keys in the tree.
+    pub entry_count: u64,
+
+    /// Account allowed to insert and delete keys.
+    pub authority: Pubkey,
+
+    /// The Merkle root (Solana `Hash`).
+    pub root: Hash,
+}
+
+impl IsInitialized for SparseMerkleTreeAccount {
+    fn is_initialized(&self) -> bool {
+        self.is_initialized
+    }
+}
+
+impl SparseMerkleTreeAccount {
+    /// Account space needed for a sparse tree, whatever its number of keys.
+    pub const LEN: usize = SPARSE_ROOT_END;
+
+    /// Lamports needed to keep a sparse tree rent exempt.
+    pub fn minimum_balance(rent: &Rent) -> u64 {
+        rent.minimum_balance(Self::LEN)
+    }
+
+    /// Unpacks an initialized sparse tree account.
+    pub fn unpack(src: &[u8]) -> Result<Self, ProgramError> {
+        let account = Self::unpack_unchecked(src)?;
+        if !account.is_initialized {
+            return Err(ProgramError::UninitializedAccount);
+        }
+        Ok(account)
+    }
+
+    /// Packs `src` into `dst`.
+    pub fn pack(src: Self, dst: &mut [u8]) -> Result<(), ProgramError> {
+        if dst.len() < Self::LEN {
+            return Err(ProgramError::AccountDataTooSmall);
+        }
+
+        dst[0] = if src.is_initialized {
+            AccountKind::SparseMerkleTree
+        } else {
+            AccountKind::Uninitialized
+        } as u8;
+        dst[VERSION_START] = src.version;
+        src.config
+            .pack_into_slice(&mut dst[TREE_CONFIG_START..SPARSE_ENTRY_COUNT_START]);
+        dst[SPARSE_ENTRY_COUNT_START..SPARSE_AUTHORITY_START]
+            .copy_from_slice(&src.entry_count.to_le_bytes());
+        dst[SPARSE_AUTHORITY_START..SPARSE_ROOT_START].copy_from_slice(src.authority.as_ref());
+        dst[SPARSE_ROOT_START..SPARSE_ROOT_END].copy_from_slice(src.root.as_ref());
+        Ok(())
+    }
+
+    /// Unpacks a sparse tree account, returning a default value if it is not
+    /// yet initialized.
+    pub fn unpack_unchecked(src: &[u8]) -> Result<Self, ProgramError> {
+        let is_initialized = check_account_kind(src, AccountKind::SparseMerkleTree)?
+            == AccountKind::SparseMerkleTree;
+        if !is_initialized {
+            return Ok(SparseMerkleTreeAccount::default());
+        }
+
+        let src = src
+            .get(..Self::LEN)
+            .ok_or(ProgramError::InvalidAccountData)?;
+        let version = src[VERSION_START];
+        if version != CURRENT_SPARSE_ACCOUNT_VERSION {
+            return Err(MerkleTreeError::UnsupportedAccountVersion.into());
+        }
+
+        let config =
+            TreeConfig::unpack_from_slice(&src[TREE_CONFIG_START..SPARSE_ENTRY_COUNT_START])
+                .map_err(|_| ProgramError::InvalidAccountData)?;
+        let entry_count = u64::from_le_bytes(
+            src[SPARSE_ENTRY_COUNT_START..SPARSE_AUTHORITY_START]
+                .try_into()
+                .map_err(|_| ProgramError::InvalidAccountData)?,
+        );
+        let authority = Pubkey::new_from_array(
+            src[SPARSE_AUTHORITY_START..SPARSE_ROOT_START]
+                .try_into()
+                .map_err(|_| ProgramError::InvalidAccountData)?,
+        );
+        let root = Hash::new(&src[SPARSE_ROOT_START..SPARSE_ROOT_END]);
+
+        Ok(SparseMerkleTreeAccount {
+            is_initialized,
+            version,
+            config,
+            entry_count,
+            authority,
+            root,
+        })
+    }
+}
+
+/// One change to a concurrent tree, kept so that proofs built against the
+/// root before it can be fast-forwarded.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ChangeLog {
+    /// The root after the change.
+    pub root: Hash,
+
+    /// Nodes on the changed leaf's path after the change, from the leaf itself
+    /// up to the child of the root.
+    pub path: Vec<Hash>,
+
+    /// Index of the changed leaf.
+    pub index: u32,
+}
+
+/// State of a concurrent Merkle tree.
+///
+/// The tree has a fixed depth and is padded with empty subtrees on the right,
+/// so its proofs are those of a `MerkleTree` over the leaves padded with
+/// `MerkleHasher::empty_node` to `2^max_depth`. The account keeps a ring
+/// buffer of the latest `max_buffer_size` changes after this header, so a proof
+/// built against any root still in the buffer is accepted. Only the header and
+/// the proof of the last appended leaf are unpacked; changelog slots are read
+/// and written in place.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ConcurrentMerkleTreeAccount {
+    /// Whether the account has been initialized.
+    pub is_initialized: bool,
+
+    /// Layout version the account was written with.
+    pub version: u8,
+
+    /// Hashing scheme used for leaves and nodes.
+    pub config: TreeConfig,
+
+    /// Number of levels above the leaves; the tree holds `2^max_depth` leaves.
+    pub max_depth: u8,
 
+    /// Number of changelog slots.
+    pub max_buffer_size: u32,
+
+    /// Account allowed to append and replace leaves.
+    pub authority: Pubkey,
+
+    /// Number of changes applied since the tree was built.
+    pub sequence_number: u64,
+
+    /// Changelog slot holding the latest change.
+    pub active_index: u32,
+
+    /// Number of changelog slots in use.
+    pub buffer_size: u32,
+
+    /// Number of leaves appended so far, i.e. the index of the next one.
+    pub rightmost_index: u32,
+
+    /// The last appended leaf.
+    pub rightmost_leaf: Hash,
+
+    /// The current root (Solana `Hash`).
+    pub root: Hash,
+
+    /// Current proof of the last appended leaf, from the leaf level up.
+    pub rightmost_proof: Vec<Hash>,
+}
+
+impl IsInitialized for ConcurrentMerkleTreeAccount {
+    fn is_initialized(&self) -> bool {
+        self.is_initialized
+    }
+}
+
+impl ConcurrentMerkleTreeAccount {
+    /// Size of the fixed header preceding the rightmost proof and the changelogs.
+    pub const HEADER_LEN: usize = CONCURRENT_ROOT_END;
+
+    /// Size of one changelog slot in a tree of `max_depth` levels.
+    fn change_log_len(max_depth: u8) -> usize {
+        HASH_SIZE + max_depth as usize * HASH_SIZE + 4
+    }
+
+    /// Account space needed for a tree of `max_depth` levels keeping
+    /// `max_buffer_size` changes.
+    pub fn space_for(max_depth: u8, max_buffer_size: u32) -> usize {
+        Self::HEADER_LEN
+            + max_depth as usize * HASH_SIZE
+            + max_buffer_size as usize * Self::change_log_len(max_depth)
+    }
+
+    /// Lamports needed to keep such a tree rent exempt.
+    pub fn minimum_balance(rent: &Rent, max_depth: u8, max_buffer_size: u32) -> u64 {
+        rent.minimum_balance(Self::space_for(max_depth, max_buffer_size))
+    }
+
+    /// Unpacks an initialized concurrent tree account.
+    pub fn unpack(src: &[u8]) -> Result<Self, ProgramError> {
+        let account = Self::unpack_unchecked(src)?;
+        if !account.is_initialized {
+            return Err(ProgramError::UninitializedAccount);
+        }
+        Ok(account)
+    }
+
+    /// Packs the header and rightmost proof of `src` into `dst`, which must be
+    /// large enough for its changelog buffer as well.
+    pub fn pack(src: Self, dst: &mut [u8]) -> Result<(), ProgramError> {
+        if dst.len() < Self::space_for(src.max_depth, src.max_buffer_size) {
+            return Err(ProgramError::AccountDataTooSmall);
+        }
+        if src.rightmost_proof.len() != src.max_depth as usize {
+            return Err(ProgramError::InvalidAccountData);
+        }
+
+        dst[0] = if src.is_initialized {
+            AccountKind::ConcurrentMerkleTree
+        } else {
+            AccountKind::Uninitialized
+        } as u8;
+        dst[VERSION_START] = src.version;
+        src.config
+            .pack_into_slice(&mut dst[TREE_CONFIG_START..CONCURRENT_MAX_DEPTH_START]);
+        dst[CONCURRENT_MAX_DEPTH_START] = src.max_depth;
+        dst[CONCURRENT_MAX_BUFFER_SIZE_START..CONCURRENT_AUTHORITY_START]
+            .copy_from_slice(&src.max_buffer_size.to_le_bytes());
+        dst[CONCURRENT_AUTHORITY_START..CONCURRENT_SEQUENCE_NUMBER_START]
+            .copy_from_slice(src.authority.as_ref());
+        dst[CONCURRENT_SEQUENCE_NUMBER_START..CONCURRENT_ACTIVE_INDEX_START]
+            .copy_from_slice(&src.sequence_number.to_le_bytes());
+        dst[CONCURRENT_ACTIVE_INDEX_START..CONCURRENT_BUFFER_SIZE_START]
+            .copy_from_slice(&src.active_index.to_le_bytes());
+        dst[CONCURRENT_BUFFER_SIZE_START..CONCURRENT_RIGHTMOST_INDEX_START]
+            .copy_from_slice(&src.buffer_size.to_le_bytes());
+        dst[CONCURRENT_RIGHTMOST_INDEX_START..CONCURRENT_RIGHTMOST_LEAF_START]
+            .copy_from_slice(&src.rightmost_index.to_le_bytes());
+        dst[CONCURRENT_RIGHTMOST_LEAF_START..CONCURRENT_ROOT_START]
+            .copy_from_slice(src.rightmost_leaf.as_ref());
+        dst[CONCURRENT_ROOT_START..CONCURRENT_ROOT_END].copy_from_slice(src.root.as_ref());
+
+        let mut offset = CONCURRENT_ROOT_END;
+        for node in &src.rightmost_proof {
+            dst[offset..offset + HASH_SIZE].copy_from_slice(node.as_ref());
             offset += HASH_SIZE;
         }
+        Ok(())
     }
 
-    fn unpack_from_slice(src: &[u8]) -> Result<Self, ProgramError> {
-        // 1) Read the `is_initialized` flag.
-        let is_initialized = src.get(0).ok_or(ProgramError::InvalidAccountData)? != &0u8;
+    /// Unpacks the header and rightmost proof of a concurrent tree account,
+    /// returning a default value if it is not yet initialized.
+    pub fn unpack_unchecked(src: &[u8]) -> Result<Self, ProgramError> {
+        let is_initialized = check_account_kind(src, AccountKind::ConcurrentMerkleTree)?
+            == AccountKind::ConcurrentMerkleTree;
+        if !is_initialized {
+            return Ok(ConcurrentMerkleTreeAccount::default());
+        }
 
-        // 2) Read Merkle root (32 bytes).
-        let root_slice = src
-            .get(ROOT_START..ROOT_END)
+        let header = src
+            .get(..Self::HEADER_LEN)
             .ok_or(ProgramError::InvalidAccountData)?;
-        let root = Hash::new(
-            root_slice
+        let version = header[VERSION_START];
+        if version != CURRENT_CONCURRENT_ACCOUNT_VERSION {
+            return Err(MerkleTreeError::UnsupportedAccountVersion.into());
+        }
+
+        let read_u32 = |start: usize| {
+            header[start..start + 4]
+                .try_into()
+                .map(u32::from_le_bytes)
+                .map_err(|_| ProgramError::InvalidAccountData)
+        };
+        let config =
+            TreeConfig::unpack_from_slice(&header[TREE_CONFIG_START..CONCURRENT_MAX_DEPTH_START])
+                .map_err(|_| ProgramError::InvalidAccountData)?;
+        let max_depth = header[CONCURRENT_MAX_DEPTH_START];
+        let max_buffer_size = read_u32(CONCURRENT_MAX_BUFFER_SIZE_START)?;
+        if max_depth == 0 || max_depth > MAX_CONCURRENT_TREE_DEPTH || max_buffer_size == 0 {
+            return Err(ProgramError::InvalidAccountData);
+        }
+        if src.len() < Self::space_for(max_depth, max_buffer_size) {
+            return Err(ProgramError::InvalidAccountData);
+        }
+
+        let authority = Pubkey::new_from_array(
+            header[CONCURRENT_AUTHORITY_START..CONCURRENT_SEQUENCE_NUMBER_START]
                 .try_into()
                 .map_err(|_| ProgramError::InvalidAccountData)?,
         );
+        let sequence_number = u64::from_le_bytes(
+            header[CONCURRENT_SEQUENCE_NUMBER_START..CONCURRENT_ACTIVE_INDEX_START]
+                .try_into()
+                .map_err(|_| ProgramError::InvalidAccountData)?,
+        );
+        let active_index = read_u32(CONCURRENT_ACTIVE_INDEX_START)?;
+        let buffer_size = read_u32(CONCURRENT_BUFFER_SIZE_START)?;
+        let rightmost_index = read_u32(CONCURRENT_RIGHTMOST_INDEX_START)?;
+        let rightmost_leaf =
+            Hash::new(&header[CONCURRENT_RIGHTMOST_LEAF_START..CONCURRENT_ROOT_START]);
+        let root = Hash::new(&header[CONCURRENT_ROOT_START..CONCURRENT_ROOT_END]);
+        let rightmost_proof = src
+            [CONCURRENT_ROOT_END..CONCURRENT_ROOT_END + max_depth as usize * HASH_SIZE]
+            .chunks_exact(HASH_SIZE)
+            .map(Hash::new)
+            .collect();
 
-        // 3) Read leaves (32 bytes each).
-        let mut leaves = Vec::new();
-        let mut offset = ROOT_END;
-        while offset < src.len() {
-            let leaf_end = offset + HASH_SIZE;
-            let chunk = src
-                .get(offset..leaf_end)
-                .ok_or(ProgramError::InvalidAccountData)?;
-
-            let leaf = Hash::new(
-                chunk
+        Ok(ConcurrentMerkleTreeAccount {
+            is_initialized,
+            version,
+            config,
+            max_depth,
+            max_buffer_size,
+            authority,
+            sequence_number,
+            active_index,
+            buffer_size,
+            rightmost_index,
+            rightmost_leaf,
+            root,
+            rightmost_proof,
+        })
+    }
+
+    /// Offset of changelog slot `slot` in the account data.
+    fn change_log_start(&self, slot: u32) -> usize {
+        CONCURRENT_ROOT_END
+            + self.max_depth as usize * HASH_SIZE
+            + slot as usize * Self::change_log_len(self.max_depth)
+    }
+
+    /// Changelog slot holding the change made `age` changes before the latest.
+    fn slot_for_age(&self, age: u32) -> u32 {
+        (self.active_index + self.max_buffer_size - age) % self.max_buffer_size
+    }
+
+    /// Reads changelog slot `slot` from the account data in `src`.
+    pub fn change_log(&self, src: &[u8], slot: u32) -> Result<ChangeLog, ProgramError> {
+        let start = self.change_log_start(slot);
+        let path_end = start + HASH_SIZE + self.max_depth as usize * HASH_SIZE;
+        let data = src
+            .get(start..path_end + 4)
+            .ok_or(ProgramError::InvalidAccountData)?;
+
+        Ok(ChangeLog {
+            root: Hash::new(&data[..HASH_SIZE]),
+            path: data[HASH_SIZE..path_end - start]
+                .chunks_exact(HASH_SIZE)
+                .map(Hash::new)
+                .collect(),
+            index: u32::from_le_bytes(
+                data[path_end - start..]
                     .try_into()
                     .map_err(|_| ProgramError::InvalidAccountData)?,
+            ),
+        })
+    }
+
+    /// Writes `change_log` into changelog slot `slot` of the account data in `dst`.
+    pub fn set_change_log(
+        &self,
+        dst: &mut [u8],
+        slot: u32,
+        change_log: &ChangeLog,
+    ) -> Result<(), ProgramError> {
+        if change_log.path.len() != self.max_depth as usize {
+            return Err(ProgramError::InvalidArgument);
+        }
+        let start = self.change_log_start(slot);
+        let data = dst
+            .get_mut(start..start + Self::change_log_len(self.max_depth))
+            .ok_or(ProgramError::AccountDataTooSmall)?;
+
+        data[..HASH_SIZE].copy_from_slice(change_log.root.as_ref());
+        let mut offset = HASH_SIZE;
+        for node in &change_log.path {
+            data[offset..offset + HASH_SIZE].copy_from_slice(node.as_ref());
+            offset += HASH_SIZE;
+        }
+        data[offset..].copy_from_slice(&change_log.index.to_le_bytes());
+        Ok(())
+    }
+
+    /// Resets the tree to its empty state, with the empty root as its only change.
+    pub fn initialize_empty<H: MerkleHasher>(
+        &mut self,
+        hasher: &H,
+        dst: &mut [u8],
+    ) -> Result<(), ProgramError> {
+        let depth = self.max_depth as usize;
+        let empty_hashes = empty_subtree_hashes(hasher, depth);
+
+        self.sequence_number = 0;
+        self.active_index = 0;
+        self.buffer_size = 1;
+        self.rightmost_index = 0;
+        self.rightmost_leaf = empty_hashes[0];
+        self.root = empty_hashes[depth];
+        self.rightmost_proof = empty_hashes[..depth].to_vec();
+
+        let change_log = ChangeLog {
+            root: self.root,
+            path: empty_hashes[..depth].to_vec(),
+            index: 0,
+        };
+        self.set_change_log(dst, 0, &change_log)
+    }
+
+    /// Appends `leaf` after the last appended leaf. No proof is needed: the
+    /// new leaf's proof is derived from the last one's.
+    pub fn append<H: MerkleHasher>(
+        &mut self,
+        hasher: &H,
+        dst: &mut [u8],
+        leaf: Hash,
+    ) -> Result<(), ProgramError> {
+        let depth = self.max_depth as usize;
+        if u64::from(self.rightmost_index) >= 1 << depth {
+            return Err(MerkleTreeError::TreeCapacityExceeded.into());
+        }
+        let index = self.rightmost_index;
+
+        // Below `intersection` the new leaf only has empty subtrees on its
+        // right; above it, it shares its siblings with the last leaf, whose
+        // ancestor at `intersection` is the new leaf's sibling there.
+        let intersection = (index.trailing_zeros() as usize).min(depth);
+        let mut proof = self.rightmost_proof.clone();
+        if intersection < depth {
+            let (_, ancestor) = path_to_root(
+                hasher,
+                self.rightmost_leaf,
+                index - 1,
+                &proof[..intersection],
             );
-            leaves.push(leaf);
+            proof[intersection] = ancestor;
+        }
+        let empty_hashes = empty_subtree_hashes(hasher, intersection);
+        proof[..intersection].copy_from_slice(&empty_hashes[..intersection]);
+
+        let (path, root) = path_to_root(hasher, leaf, index, &proof);
+        self.rightmost_proof = proof;
+        self.rightmost_leaf = leaf;
+        self.rightmost_index += 1;
+        self.push_change_log(dst, ChangeLog { root, path, index })
+    }
+
+    /// Replaces `old_leaf` at `index` with `new_leaf`. `proof` may have been
+    /// built against any `root` still in the changelog buffer; it is
+    /// fast-forwarded through the changes made since.
+    #[allow(clippy::too_many_arguments)]
+    pub fn replace_leaf<H: MerkleHasher>(
+        &mut self,
+        hasher: &H,
+        dst: &mut [u8],
+        root: Hash,
+        index: u32,
+        old_leaf: Hash,
+        new_leaf: Hash,
+        proof: &[Hash],
+    ) -> Result<(), ProgramError> {
+        if index >= self.rightmost_index {
+            return Err(MerkleTreeError::InvalidLeafIndex.into());
+        }
+        if proof.len() != self.max_depth as usize {
+            return Err(MerkleTreeError::InvalidProof.into());
+        }
+
+        // Find the change that produced `root`, newest first.
+        let mut root_age = None;
+        for age in 0..self.buffer_size {
+            if self.change_log(dst, self.slot_for_age(age))?.root == root {
+                root_age = Some(age);
+                break;
+            }
+        }
+        let root_age = root_age.ok_or(MerkleTreeError::StaleRoot)?;
+
+        // Replay every later change, oldest first. Each one changed the node
+        // of our path's sibling at the level where the two paths meet.
+        let mut proof = proof.to_vec();
+        for age in (0..root_age).rev() {
+            let change_log = self.change_log(dst, self.slot_for_age(age))?;
+            if change_log.index == index {
+                return Err(MerkleTreeError::LeafMismatch.into());
+            }
+            let level = intersection_level(index, change_log.index);
+            proof[level] = change_log.path[level];
+        }
+
+        // The updated proof must lead from the old leaf to the current root.
+        let (_, current_root) = path_to_root(hasher, old_leaf, index, &proof);
+        if current_root != self.root {
+            return Err(MerkleTreeError::InvalidProof.into());
+        }
+
+        // Keep the proof of the last appended leaf in step with the change.
+        let (path, root) = path_to_root(hasher, new_leaf, index, &proof);
+        let last_index = self.rightmost_index - 1;
+        if index == last_index {
+            self.rightmost_leaf = new_leaf;
+        } else {
+            let level = intersection_level(index, last_index);
+            self.rightmost_proof[level] = path[level];
+        }
+        self.push_change_log(dst, ChangeLog { root, path, index })
+    }
+
+    /// Records `change_log` as the latest change, evicting the oldest one
+    /// once the buffer is full.
+    fn push_change_log(
+        &mut self,
+        dst: &mut [u8],
+        change_log: ChangeLog,
+    ) -> Result<(), ProgramError> {
+        self.active_index = (self.active_index + 1) % self.max_buffer_size;
+        self.buffer_size = (self.buffer_size + 1).min(self.max_buffer_size);
+        self.sequence_number += 1;
+        self.root = change_log.root;
+        self.set_change_log(dst, self.active_index, &change_log)
+    }
+}
+
+/// State of an incremental, append-only Merkle tree.
+///
+/// Like a concurrent tree, the tree has a fixed depth and is padded with empty
+/// subtrees on the right, so its root is that of a `MerkleTree` over the leaves
+/// padded with `MerkleHasher::empty_node` to `2^depth`. Leaves are not stored:
+/// the account only keeps the left sibling of the next leaf's path at each
+/// level ("filled subtrees"), so appending costs `depth` hashes and the account
+/// size does not depend on the number of leaves.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct IncrementalMerkleTreeAccount {
+    /// Whether the account has been initialized.
+    pub is_initialized: bool,
+
+    /// Layout version the account was written with.
+    pub version: u8,
+
+    /// Hashing scheme used for leaves and nodes.
+    pub config: TreeConfig,
+
+    /// Number of levels above the leaves; the tree holds `2^depth` leaves.
+    pub depth: u8,
+
+    /// Account allowed to append leaves.
+    pub authority: Pubkey,
+
+    /// Number of leaves appended so far, i.e. the index of the next one.
+    pub leaf_count: u64,
+
+    /// The current root (Solana `Hash`).
+    pub root: Hash,
+
+    /// At each level from the leaves up, the root of the last complete left
+    /// subtree, or of an empty subtree if there is none yet.
+    pub filled_subtrees: Vec<Hash>,
+}
+
+impl IsInitialized for IncrementalMerkleTreeAccount {
+    fn is_initialized(&self) -> bool {
+        self.is_initialized
+    }
+}
+
+impl IncrementalMerkleTreeAccount {
+    /// Size of the fixed header preceding the filled subtrees.
+    pub const HEADER_LEN: usize = INCREMENTAL_ROOT_END;
+
+    /// Account space needed for a tree of `depth` levels, whatever its number
+    /// of leaves.
+    pub fn space_for(depth: u8) -> usize {
+        Self::HEADER_LEN + depth as usize * HASH_SIZE
+    }
+
+    /// Lamports needed to keep such a tree rent exempt.
+    pub fn minimum_balance(rent: &Rent, depth: u8) -> u64 {
+        rent.minimum_balance(Self::space_for(depth))
+    }
+
+    /// Number of leaves the tree can hold.
+    pub fn capacity(&self) -> u64 {
+        1 << self.depth
+    }
+
+    /// Resets the tree to its empty state.
+    pub fn initialize_empty<H: MerkleHasher>(&mut self, hasher: &H) {
+        let depth = self.depth as usize;
+        let empty_hashes = empty_subtree_hashes(hasher, depth);
+
+        self.leaf_count = 0;
+        self.root = empty_hashes[depth];
+        self.filled_subtrees = empty_hashes[..depth].to_vec();
+    }
+
+    /// Appends `leaf` after the last appended leaf, failing once the tree
+    /// holds `2^depth` leaves. `empty_hashes` are the hasher's
+    /// `empty_subtree_hashes` for at least `depth` levels, so that appending
+    /// several leaves computes them only once.
+    pub fn append<H: MerkleHasher>(
+        &mut self,
+        hasher: &H,
+        empty_hashes: &[Hash],
+        leaf: Hash,
+    ) -> Result<(), ProgramError> {
+        if self.leaf_count >= self.capacity() {
+            return Err(MerkleTreeError::TreeCapacityExceeded.into());
+        }
+
+        // Everything right of the new leaf is still empty, and everything left
+        // of it is summarized by the filled subtrees.
+        let mut node = leaf;
+        for (level, filled) in self.filled_subtrees.iter_mut().enumerate() {
+            node = if (self.leaf_count >> level) & 1 == 0 {
+                *filled = node;
+                hasher.hash_node(&node, &empty_hashes[level])
+            } else {
+                hasher.hash_node(filled, &node)
+            };
+        }
+
+        self.root = node;
+        self.leaf_count += 1;
+        Ok(())
+    }
+
+    /// Unpacks an initialized incremental tree account.
+    pub fn unpack(src: &[u8]) -> Result<Self, ProgramError> {
+        let account = Self::unpack_unchecked(src)?;
+        if !account.is_initialized {
+            return Err(ProgramError::UninitializedAccount);
+        }
+        Ok(account)
+    }
+
+    /// Packs `src` into `dst`.
+    pub fn pack(src: Self, dst: &mut [u8]) -> Result<(), ProgramError> {
+        if dst.len() < Self::space_for(src.depth) {
+            return Err(ProgramError::AccountDataTooSmall);
+        }
+        if src.filled_subtrees.len() != src.depth as usize {
+            return Err(ProgramError::InvalidAccountData);
+        }
 
+        dst[0] = if src.is_initialized {
+            AccountKind::IncrementalMerkleTree
+        } else {
+            AccountKind::Uninitialized
+        } as u8;
+        dst[VERSION_START] = src.version;
+        src.config
+            .pack_into_slice(&mut dst[TREE_CONFIG_START..INCREMENTAL_DEPTH_START]);
+        dst[INCREMENTAL_DEPTH_START] = src.depth;
+        dst[INCREMENTAL_AUTHORITY_START..INCREMENTAL_LEAF_COUNT_START]
+            .copy_from_slice(src.authority.as_ref());
+        dst[INCREMENTAL_LEAF_COUNT_START..INCREMENTAL_ROOT_START]
+            .copy_from_slice(&src.leaf_count.to_le_bytes());
+        dst[INCREMENTAL_ROOT_START..INCREMENTAL_ROOT_END].copy_from_slice(src.root.as_ref());
+
+        let mut offset = INCREMENTAL_ROOT_END;
+        for node in &src.filled_subtrees {
+            dst[offset..offset + HASH_SIZE].copy_from_slice(node.as_ref());
             offset += HASH_SIZE;
         }
+        Ok(())
+    }
 
-        Ok(MerkleTreeAccount {
+    /// Unpacks an incremental tree account, returning a default value if it is
+    /// not yet initialized.
+    pub fn unpack_unchecked(src: &[u8]) -> Result<Self, ProgramError> {
+        let is_initialized = check_account_kind(src, AccountKind::IncrementalMerkleTree)?
+            == AccountKind::IncrementalMerkleTree;
+        if !is_initialized {
+            return Ok(IncrementalMerkleTreeAccount::default());
+        }
+
+        let header = src
+            .get(..Self::HEADER_LEN)
+            .ok_or(ProgramError::InvalidAccountData)?;
+        let version = header[VERSION_START];
+        if version != CURRENT_INCREMENTAL_ACCOUNT_VERSION {
+            return Err(MerkleTreeError::UnsupportedAccountVersion.into());
+        }
+
+        let config =
+            TreeConfig::unpack_from_slice(&header[TREE_CONFIG_START..INCREMENTAL_DEPTH_START])
+                .map_err(|_| ProgramError::InvalidAccountData)?;
+        let depth = header[INCREMENTAL_DEPTH_START];
+        if depth == 0 || depth > MAX_INCREMENTAL_TREE_DEPTH {
+            return Err(ProgramError::InvalidAccountData);
+        }
+        let filled_subtrees = src
+            .get(INCREMENTAL_ROOT_END..Self::space_for(depth))
+            .ok_or(ProgramError::InvalidAccountData)?
+            .chunks_exact(HASH_SIZE)
+            .map(Hash::new)
+            .collect();
+
+        let authority = Pubkey::new_from_array(
+            header[INCREMENTAL_AUTHORITY_START..INCREMENTAL_LEAF_COUNT_START]
+                .try_into()
+                .map_err(|_| ProgramError::InvalidAccountData)?,
+        );
+        let leaf_count = u64::from_le_bytes(
+            header[INCREMENTAL_LEAF_COUNT_START..INCREMENTAL_ROOT_START]
+                .try_into()
+                .map_err(|_| ProgramError::InvalidAccountData)?,
+        );
+        let root = Hash::new(&header[INCREMENTAL_ROOT_START..INCREMENTAL_ROOT_END]);
+
+        Ok(IncrementalMerkleTreeAccount {
             is_initialized,
+            version,
+            config,
+            depth,
+            authority,
+            leaf_count,
             root,
-            leaves,
+            filled_subtrees,
         })
     }
 }
+
+/// State of a Merkle mountain range (see `mmr`).
+///
+/// Leaves are not stored; the account keeps the peaks, one slot per bit of the
+/// leaf count, and their bagged root.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct MerkleMountainRangeAccount {
+    /// Whether the account has been initialized.
+    pub is_initialized: bool,
+
+    /// Layout version the account was written with.
+    pub version: u8,
+
+    /// Hashing scheme used for leaves and nodes.
+    pub config: TreeConfig,
+
+    /// Account allowed to append leaves.
+    pub authority: Pubkey,
+
+    /// Number of leaves appended so far, i.e. the index of the next one.
+    pub leaf_count: u32,
+
+    /// The peaks bagged into a single root (Solana `Hash`), a one-hash
+    /// commitment to the whole range for readers of the account.
+    /// `VerifyMmrProof` checks proofs against `peaks` instead.
+    pub root: Hash,
+
+    /// Roots of the perfect subtrees, highest first.
+    pub peaks: Vec<Hash>,
+}
+
+impl IsInitialized for MerkleMountainRangeAccount {
+    fn is_initialized(&self) -> bool {
+        self.is_initialized
+    }
+}
+
+impl MerkleMountainRangeAccount {
+    /// Account space needed for a Merkle mountain range, whatever its number
+    /// of leaves.
+    pub const LEN: usize = MMR_PEAKS_END;
+
+    /// Lamports needed to keep a Merkle mountain range rent exempt.
+    pub fn minimum_balance(rent: &Rent) -> u64 {
+        rent.minimum_balance(Self::LEN)
+    }
+
+    /// Appends `leaf`, merging peaks as needed, and bags the new peaks.
+    pub fn append<H: MerkleHasher>(&mut self, hasher: &H, leaf: Hash) -> Result<(), ProgramError> {
+        append_to_peaks(hasher, &mut self.peaks, self.leaf_count, leaf)?;
+        self.leaf_count += 1;
+        self.root = bag_peaks(hasher, &self.peaks);
+        Ok(())
+    }
+
+    /// Unpacks an initialized Merkle mountain range account.
+    pub fn unpack(src: &[u8]) -> Result<Self, ProgramError> {
+        let account = Self::unpack_unchecked(src)?;
+        if !account.is_initialized {
+            return Err(ProgramError::UninitializedAccount);
+        }
+        Ok(account)
+    }
+
+    /// Packs `src` into `dst`.
+    pub fn pack(src: Self, dst: &mut [u8]) -> Result<(), ProgramError> {
+        if dst.len() < Self::LEN {
+            return Err(ProgramError::AccountDataTooSmall);
+        }
+        if src.peaks.len() != src.leaf_count.count_ones() as usize {
+            return Err(ProgramError::InvalidAccountData);
+        }
+
+        dst[0] = if src.is_initialized {
+            AccountKind::MerkleMountainRange
+        } else {
+            AccountKind::Uninitialized
+        } as u8;
+        dst[VERSION_START] = src.version;
+        src.config
+            .pack_into_slice(&mut dst[TREE_CONFIG_START..MMR_AUTHORITY_START]);
+        dst[MMR_AUTHORITY_START..MMR_LEAF_COUNT_START].copy_from_slice(src.authority.as_ref());
+        dst[MMR_LEAF_COUNT_START..MMR_ROOT_START].copy_from_slice(&src.leaf_count.to_le_bytes());
+        dst[MMR_ROOT_START..MMR_ROOT_END].copy_from_slice(src.root.as_ref());
+
+        // Unused peak slots are cleared so the account only depends on the range.
+        dst[MMR_ROOT_END..MMR_PEAKS_END].fill(0);
+        let mut offset = MMR_ROOT_END;
+        for peak in &src.peaks {
+            dst[offset..offset + HASH_SIZE].copy_from_slice(peak.as_ref());
+            offset += HASH_SIZE;
+        }
+        Ok(())
+    }
+
+    /// Unpacks a Merkle mountain range account, returning a default value if
+    /// it is not yet initialized.
+    pub fn unpack_unchecked(src: &[u8]) -> Result<Self, ProgramError> {
+        let is_initialized = check_account_kind(src, AccountKind::MerkleMountainRange)?
+            == AccountKind::MerkleMountainRange;
+        if !is_initialized {
+            return Ok(MerkleMountainRangeAccount::default());
+        }
+
+        let src = src
+            .get(..Self::LEN)
+            .ok_or(ProgramError::InvalidAccountData)?;
+        let version = src[VERSION_START];
+        if version != CURRENT_MMR_ACCOUNT_VERSION {
+            return Err(MerkleTreeError::UnsupportedAccountVersion.into());
+        }
+
+        let config = TreeConfig::unpack_from_slice(&src[TREE_CONFIG_START..MMR_AUTHORITY_START])
+            .map_err(|_| ProgramError::InvalidAccountData)?;
+        let authority = Pubkey::new_from_array(
+            src[MMR_AUTHORITY_START..MMR_LEAF_COUNT_START]
+                .try_into()
+                .map_err(|_| ProgramError::InvalidAccountData)?,
+        );
+        let leaf_count = u32::from_le_bytes(
+            src[MMR_LEAF_COUNT_START..MMR_ROOT_START]
+                .try_into()
+                .map_err(|_| ProgramError::InvalidAccountData)?,
+        );
+        let root = Hash::new(&src[MMR_ROOT_START..MMR_ROOT_END]);
+        let peaks = src[MMR_ROOT_END..MMR_PEAKS_END]
+            .chunks_exact(HASH_SIZE)
+            .take(leaf_count.count_ones() as usize)
+            .map(Hash::new)
+            .collect();
+
+        Ok(MerkleMountainRangeAccount {
+            is_initialized,
+            version,
+            config,
+            authority,
+            leaf_count,
+            root,
+            peaks,
+        })
+    }
+}
+
+/// Hashes `leaf` up through `proof`, returning the nodes on its path (the leaf
+/// first) and the node reached above the last sibling.
+fn path_to_root<H: MerkleHasher>(
+    hasher: &H,
+    leaf: Hash,
+    index: u32,
+    proof: &[Hash],
+) -> (Vec<Hash>, Hash) {
+    let mut path = Vec::with_capacity(proof.len());
+    let mut node = leaf;
+    for (level, sibling) in proof.iter().enumerate() {
+        path.push(node);
+        node = if (index >> level) & 1 == 0 {
+            hasher.hash_node(&node, sibling)
+        } else {
+            hasher.hash_node(sibling, &node)
+        };
+    }
+    (path, node)
+}
+
+/// Level at which the paths of two different leaves meet: the children of
+/// their lowest common ancestor sit there.
+fn intersection_level(index: u32, other: u32) -> usize {
+    (u32::BITS - 1 - (index ^ other).leading_zeros()) as usize
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tree(leaf_count: usize, root_history_size: u8) -> MerkleTreeAccount {
+        let leaves = (0..leaf_count)
+            .map(|i| Hash::new_from_array([i as u8 + 1; 32]))
+            .collect::<Vec<_>>();
+        MerkleTreeAccount {
+            is_initialized: true,
+            version: CURRENT_ACCOUNT_VERSION,
+            leaf_count: leaf_count as u32,
+            depth: tree_depth(leaf_count),
+            authority: Pubkey::new_unique(),
+            root: Hash::new_from_array([0xAA; 32]),
+            root_history_size,
+            leaves,
+            ..MerkleTreeAccount::default()
+        }
+    }
+
+    #[test]
+    fn space_for_round_trips_through_capacity() {
+        for root_history_size in [0, 1, 8] {
+            for capacity in [0, 1, 30, 31, 1000] {
+                let space = MerkleTreeAccount::space_for(capacity, root_history_size);
+                assert_eq!(
+                    MerkleTreeAccount::capacity(space, root_history_size),
+                    capacity
+                );
+                assert_eq!(
+                    MerkleTreeAccount::capacity(space - 1, root_history_size),
+                    capacity.saturating_sub(1)
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn pack_accepts_leaves_up_to_capacity() {
+        for root_history_size in [0, 3] {
+            let capacity = 31;
+            let mut data = vec![0; MerkleTreeAccount::space_for(capacity, root_history_size)];
+
+            let mut account = tree(capacity, root_history_size);
+            account.set_root(Hash::new_from_array([0xBB; 32]), capacity as u32);
+            MerkleTreeAccount::pack(account.clone(), &mut data).unwrap();
+            assert_eq!(MerkleTreeAccount::unpack(&data).unwrap(), account);
+
+            let overfull = tree(capacity + 1, root_history_size);
+            assert_eq!(
+                MerkleTreeAccount::pack(overfull, &mut data),
+                Err(MerkleTreeError::TreeCapacityExceeded.into())
+            );
+        }
+    }
+
+    #[test]
+    fn root_history_is_reserved_ahead_of_the_leaves() {
+        // Room for four leaves without history only fits two once a 36-byte
+        // historic root is reserved.
+        let data_len = MerkleTreeAccount::space_for(4, 0);
+        assert_eq!(MerkleTreeAccount::capacity(data_len, 1), 2);
+
+        let mut data = vec![0; data_len];
+        assert_eq!(
+            MerkleTreeAccount::pack(tree(3, 1), &mut data),
+            Err(MerkleTreeError::TreeCapacityExceeded.into())
+        );
+        MerkleTreeAccount::pack(tree(2, 1), &mut data).unwrap();
+    }
+}
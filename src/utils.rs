@@ -1,97 +1,774 @@
-use crate::errors::MerkleTreeError;
+use crate::{
+    errors::MerkleTreeError,
+    state::{HashFunction, NodeHashing, TreeConfig},
+};
+use borsh::{BorshDeserialize, BorshSerialize};
 use solana_program::{
-    hash::{hash, Hash},
+    blake3,
+    hash::{self, Hash},
+    keccak,
     program_error::ProgramError,
 };
 
-/// Hash a leaf's bytes and produce a `Hash` (32 bytes).
-pub fn hash_leaf(leaf_data: &[u8]) -> Hash {
-    Hash::new(&hash(leaf_data).to_bytes())
+/// Prefix prepended to leaf data when the tree is domain separated.
+pub const LEAF_PREFIX: u8 = 0x00;
+
+/// Prefix prepended to a pair of child hashes when the tree is domain separated.
+pub const NODE_PREFIX: u8 = 0x01;
+
+/// How the leaves of a tree are arranged beneath its root.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TreeLayout {
+    /// Every leaf sits on the bottom level, in order, and the last node of an
+    /// odd-sized level is paired with itself.
+    DuplicateOdd,
+
+    /// OpenZeppelin's complete binary tree: every level above the bottom one
+    /// is full. Only the first leaves are paired on the bottom level; the
+    /// others sit one level up, ahead of the parents of those pairs.
+    Complete,
+}
+
+/// Hashing primitives a Merkle tree is built from.
+///
+/// Only `hashv` is required; the defaults hash leaves and nodes as plain
+/// concatenations in a `DuplicateOdd` layout, which implementors may override
+/// to add prefixes, to reorder children or to arrange the leaves differently.
+pub trait MerkleHasher {
+    /// Hash the concatenation of `vals`.
+    fn hashv(&self, vals: &[&[u8]]) -> Hash;
+
+    /// Hash a leaf's bytes and produce a `Hash` (32 bytes).
+    fn hash_leaf(&self, leaf_data: &[u8]) -> Hash {
+        self.hashv(&[leaf_data])
+    }
+
+    /// Concatenate two 32-byte child hashes and hash them again.
+    fn hash_node(&self, left: &Hash, right: &Hash) -> Hash {
+        self.hashv(&[left.as_ref(), right.as_ref()])
+    }
+
+    /// Value of a node with no leaves beneath it.
+    fn empty_node(&self) -> Hash {
+        Hash::default()
+    }
+
+    /// How the leaves of a tree are arranged beneath its root.
+    fn layout(&self) -> TreeLayout {
+        TreeLayout::DuplicateOdd
+    }
+}
+
+/// SHA-256, the Solana-native `hash` syscall.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Sha256Hasher;
+
+impl MerkleHasher for Sha256Hasher {
+    fn hashv(&self, vals: &[&[u8]]) -> Hash {
+        hash::hashv(vals)
+    }
+}
+
+/// Keccak-256, as used by the EVM.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Keccak256Hasher;
+
+impl MerkleHasher for Keccak256Hasher {
+    fn hashv(&self, vals: &[&[u8]]) -> Hash {
+        Hash::new_from_array(keccak::hashv(vals).to_bytes())
+    }
+}
+
+/// BLAKE3.
+///
+/// Intended for off-chain use: the `blake3` syscall is not enabled on the
+/// public clusters, so it is not offered as an on-chain `HashFunction`.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Blake3Hasher;
+
+impl MerkleHasher for Blake3Hasher {
+    fn hashv(&self, vals: &[&[u8]]) -> Hash {
+        Hash::new_from_array(blake3::hashv(vals).to_bytes())
+    }
+}
+
+/// A tree's configuration hashes with its selected function, applying the
+/// domain-separation prefixes and pair ordering it was built with.
+impl MerkleHasher for TreeConfig {
+    fn hashv(&self, vals: &[&[u8]]) -> Hash {
+        match self.hash_function {
+            HashFunction::Sha256 => Sha256Hasher.hashv(vals),
+            HashFunction::Keccak256 => Keccak256Hasher.hashv(vals),
+        }
+    }
+
+    fn hash_leaf(&self, leaf_data: &[u8]) -> Hash {
+        if self.domain_separated {
+            self.hashv(&[&[LEAF_PREFIX], leaf_data])
+        } else {
+            self.hashv(&[leaf_data])
+        }
+    }
+
+    /// With `NodeHashing::SortedPair` the smaller hash always goes first, so
+    /// the result does not depend on which side each child sits.
+    fn hash_node(&self, left: &Hash, right: &Hash) -> Hash {
+        let (left, right) = match self.node_hashing {
+            NodeHashing::SortedPair if right < left => (right, left),
+            _ => (left, right),
+        };
+
+        if self.domain_separated {
+            self.hashv(&[&[NODE_PREFIX], left.as_ref(), right.as_ref()])
+        } else {
+            self.hashv(&[left.as_ref(), right.as_ref()])
+        }
+    }
+
+    /// `Keccak256` trees with `SortedPair` hashing are laid out like
+    /// OpenZeppelin's, so that their roots match the ones built for the EVM.
+    fn layout(&self) -> TreeLayout {
+        match (self.hash_function, self.node_hashing) {
+            (HashFunction::Keccak256, NodeHashing::SortedPair) => TreeLayout::Complete,
+            _ => TreeLayout::DuplicateOdd,
+        }
+    }
+}
+
+/// A Merkle proof as returned by the `BuildProof` instruction.
+#[derive(BorshSerialize, BorshDeserialize, Clone, Debug, PartialEq)]
+pub struct MerkleProof {
+    /// Index of the proven leaf. `leaf_path` turns it into the path
+    /// directions the siblings are combined in.
+    pub leaf_index: u32,
+
+    /// Hash of the proven leaf as stored in the tree.
+    pub leaf: Hash,
+
+    /// Sibling hashes, from the leaf level up to the root.
+    pub siblings: Vec<Hash>,
 }
 
-/// Concatenate two 32-byte hashes and hash them again.
-fn hash_two_hashes(left: Hash, right: Hash) -> Hash {
-    let mut combined = Vec::with_capacity(64);
-    combined.extend_from_slice(left.as_ref());
-    combined.extend_from_slice(right.as_ref());
-    hash_leaf(&combined)
+/// Number of levels above the leaves of a tree with `leaf_count` leaves,
+/// which is also the number of siblings in each of its proofs.
+pub fn tree_depth(leaf_count: usize) -> u8 {
+    leaf_count.next_power_of_two().trailing_zeros() as u8
+}
+
+/// Number of leaves on the bottom level of a tree with `leaf_count` leaves.
+///
+/// In a complete tree, that is as many as it takes for the level above to be
+/// full; the other leaves sit on that level.
+fn bottom_width<H: MerkleHasher>(hasher: &H, leaf_count: usize) -> usize {
+    match hasher.layout() {
+        TreeLayout::Complete if leaf_count > 1 => 2 * leaf_count - leaf_count.next_power_of_two(),
+        _ => leaf_count,
+    }
+}
+
+/// Path from the leaf at `leaf_index` to the root of a tree with `leaf_count`
+/// leaves, or `None` if there is no such leaf.
+///
+/// The path is an index whose bit `i` tells whether the node `i` levels up is
+/// a left (`0`) or right (`1`) child, and its number of levels, which is also
+/// the length of the leaf's proof. In a `DuplicateOdd` tree, that is the leaf
+/// index and the tree depth; in a complete tree, the leaves that are not on
+/// the bottom level have a path one level shorter.
+pub fn leaf_path<H: MerkleHasher>(
+    hasher: &H,
+    leaf_index: u32,
+    leaf_count: u32,
+) -> Option<(u32, usize)> {
+    if leaf_index >= leaf_count {
+        return None;
+    }
+
+    let depth = tree_depth(leaf_count as usize) as usize;
+    let bottom_width = bottom_width(hasher, leaf_count as usize) as u32;
+    Some(match hasher.layout() {
+        TreeLayout::DuplicateOdd => (leaf_index, depth),
+        TreeLayout::Complete if leaf_index >= bottom_width => {
+            (leaf_index - bottom_width, depth - 1)
+        }
+        // The bottom level of a complete tree is the right end of a full level.
+        TreeLayout::Complete => {
+            let offset = (1u64 << depth) - u64::from(bottom_width);
+            ((offset + u64::from(leaf_index)) as u32, depth)
+        }
+    })
 }
 
 /// Build a Merkle root from a list of `Hash` leaves.
-pub fn build_merkle_root(leaves: &[Hash]) -> Result<Hash, ProgramError> {
+pub fn build_merkle_root<H: MerkleHasher>(
+    hasher: &H,
+    leaves: &[Hash],
+) -> Result<Hash, ProgramError> {
     if leaves.is_empty() {
         return Err(MerkleTreeError::EmptyTree.into());
     }
+    if leaves.len() == 1 {
+        return Ok(leaves[0]);
+    }
 
-    let mut current_level = leaves.to_vec();
+    let mut current_level = hash_leaf_level(hasher, leaves);
     while current_level.len() > 1 {
-        let mut next_level = Vec::with_capacity((current_level.len() + 1) / 2);
-        for pair in current_level.chunks(2) {
-            let left = pair[0];
-            let right = if pair.len() > 1 { pair[1] } else { pair[0] };
-            next_level.push(hash_two_hashes(left, right));
-        }
-        current_level = next_level;
+        current_level = hash_level(hasher, &current_level);
     }
 
     Ok(current_level[0])
 }
 
+/// Hashes at least two `leaves` into the level above them.
+///
+/// In a complete tree, only the leaves on the bottom level are paired; the
+/// others are already on the level above, where they come first.
+pub fn hash_leaf_level<H: MerkleHasher>(hasher: &H, leaves: &[Hash]) -> Vec<Hash> {
+    let (bottom, upper) = leaves.split_at(bottom_width(hasher, leaves.len()));
+    upper
+        .iter()
+        .copied()
+        .chain(hash_level(hasher, bottom))
+        .collect()
+}
+
+/// Hashes each pair of nodes of `level` into the level above it. The last node
+/// of an odd-sized level is paired with itself, which never happens above the
+/// leaves of a complete tree.
+pub fn hash_level<H: MerkleHasher>(hasher: &H, level: &[Hash]) -> Vec<Hash> {
+    level
+        .chunks(2)
+        .map(|pair| {
+            let left = pair[0];
+            let right = if pair.len() > 1 { pair[1] } else { pair[0] };
+            hasher.hash_node(&left, &right)
+        })
+        .collect()
+}
+
 /// Generates a Merkle proof for a specific leaf index.
-pub fn build_proof(leaf_index: usize, leaves: &[Hash]) -> Result<Vec<Hash>, ProgramError> {
+///
+/// The proof contains exactly one sibling per level on the leaf's path. When
+/// the node is the last one of an odd-sized level, its sibling is the node
+/// itself, mirroring the duplication performed by `build_merkle_root`.
+pub fn build_proof<H: MerkleHasher>(
+    hasher: &H,
+    leaf_index: usize,
+    leaves: &[Hash],
+) -> Result<Vec<Hash>, ProgramError> {
     if leaves.is_empty() || leaf_index >= leaves.len() {
         return Err(ProgramError::InvalidArgument);
     }
 
     let mut proof = Vec::new();
-    let mut level = leaves.to_vec();
-    let mut index = leaf_index;
+    if leaves.len() == 1 {
+        return Ok(proof);
+    }
 
+    let mut index = push_leaf_sibling(hasher, leaves, leaf_index, &mut proof);
+    let mut level = hash_leaf_level(hasher, leaves);
     while level.len() > 1 {
-        let mut next_level = Vec::new();
-
-        for chunk in level.chunks(2) {
-            let left = chunk[0];
-            let right = if chunk.len() > 1 { chunk[1] } else { chunk[0] };
+        proof.push(sibling_at(&level, index));
+        level = hash_level(hasher, &level);
+        index /= 2;
+    }
 
-            if index % 2 == 0 {
-                if chunk.len() > 1 {
-                    proof.push(right);
-                }
-            } else {
-                proof.push(left);
-            }
+    Ok(proof)
+}
 
-            next_level.push(hash_two_hashes(left, right));
-        }
+/// Appends to `proof` the sibling of the leaf at `leaf_index` among at least
+/// two `leaves`, unless the leaf is above the bottom level, and returns the
+/// index of the leaf's node on the level above.
+pub fn push_leaf_sibling<H: MerkleHasher>(
+    hasher: &H,
+    leaves: &[Hash],
+    leaf_index: usize,
+    proof: &mut Vec<Hash>,
+) -> usize {
+    let bottom_width = bottom_width(hasher, leaves.len());
+    if leaf_index < bottom_width {
+        proof.push(sibling_at(&leaves[..bottom_width], leaf_index));
+        leaves.len() - bottom_width + leaf_index / 2
+    } else {
+        leaf_index - bottom_width
+    }
+}
 
-        level = next_level;
-        index /= 2;
+/// Hashes of empty subtrees of up to `depth` levels, indexed by height: entry
+/// `0` is the empty leaf, entry `depth` the root of an empty tree that deep.
+pub fn empty_subtree_hashes<H: MerkleHasher>(hasher: &H, depth: usize) -> Vec<Hash> {
+    let mut hashes = Vec::with_capacity(depth + 1);
+    hashes.push(hasher.empty_node());
+    for height in 0..depth {
+        hashes.push(hasher.hash_node(&hashes[height], &hashes[height]));
     }
+    hashes
+}
 
-    Ok(proof)
+/// Sibling of the node at `index` in `level`. The sibling sits next to the
+/// node; the node itself stands in when it has no right neighbour.
+pub fn sibling_at(level: &[Hash], index: usize) -> Hash {
+    level.get(index ^ 1).copied().unwrap_or(level[index])
 }
 
-/// Recomputes the Merkle root from a `leaf_data` and `proof` (list of sibling hashes).
-pub fn recompute_merkle_root_from_proof(
+/// Recomputes the Merkle root from a `leaf_data`, its `leaf_index` and `proof`
+/// (list of sibling hashes, from the leaf level up).
+pub fn recompute_merkle_root_from_proof<H: MerkleHasher>(
+    hasher: &H,
     leaf_data: &[u8],
+    leaf_index: u32,
     proof: &[Hash],
 ) -> Result<Hash, ProgramError> {
-    if proof.is_empty() {
-        return Err(ProgramError::InvalidArgument);
-    }
+    recompute_merkle_root_from_leaf_hash(hasher, hasher.hash_leaf(leaf_data), leaf_index, proof)
+}
 
-    // Start with the hashed leaf
-    let mut current = hash_leaf(leaf_data);
+/// Recomputes the Merkle root from an already hashed leaf, its `leaf_index`
+/// and `proof` (list of sibling hashes, from the leaf level up).
+///
+/// Each bit of `leaf_index` tells whether the node at that level is a left
+/// (`0`) or right (`1`) child, so the pairs are hashed in the same order as in
+/// `build_merkle_root`.
+pub fn recompute_merkle_root_from_leaf_hash<H: MerkleHasher>(
+    hasher: &H,
+    leaf_hash: Hash,
+    leaf_index: u32,
+    proof: &[Hash],
+) -> Result<Hash, ProgramError> {
+    let mut current = leaf_hash;
+    let mut index = leaf_index;
 
     // Traverse the proof and compute the Merkle root
     for sibling in proof {
-        if current <= *sibling {
-            current = hash_two_hashes(current, *sibling);
+        if index % 2 == 0 {
+            current = hasher.hash_node(&current, sibling);
         } else {
-            current = hash_two_hashes(*sibling, current);
+            current = hasher.hash_node(sibling, &current);
         }
+        index /= 2;
+    }
+
+    // Leftover index bits mean the proof is shorter than the path to the leaf.
+    if index != 0 {
+        return Err(MerkleTreeError::InvalidLeafIndex.into());
     }
 
     Ok(current)
 }
+
+/// Recomputes the root of a tree with `leaf_count` leaves from the hash of the
+/// leaf at `leaf_index` and its proof, which must have one sibling per level
+/// on the leaf's path.
+pub fn recompute_tree_root<H: MerkleHasher>(
+    hasher: &H,
+    leaf_hash: Hash,
+    leaf_index: u32,
+    leaf_count: u32,
+    proof: &[Hash],
+) -> Result<Hash, ProgramError> {
+    let (path, levels) =
+        leaf_path(hasher, leaf_index, leaf_count).ok_or(MerkleTreeError::InvalidLeafIndex)?;
+    if proof.len() != levels {
+        return Err(MerkleTreeError::InvalidProof.into());
+    }
+    recompute_merkle_root_from_leaf_hash(hasher, leaf_hash, path, proof)
+}
+
+/// Recomputes the root of a tree with `leaf_count` leaves from the hash of the
+/// leaf at `leaf_index` and its proof.
+///
+/// Unlike `recompute_tree_root`, a node without a right neighbour is paired
+/// with itself instead of with its proof entry, so a proof taken before the
+/// leaf changed can be replayed with the new leaf hash.
+pub fn recompute_root_with_leaf_hash<H: MerkleHasher>(
+    hasher: &H,
+    leaf_hash: Hash,
+    leaf_index: u32,
+    leaf_count: u32,
+    proof: &[Hash],
+) -> Result<Hash, ProgramError> {
+    let (path, levels) =
+        leaf_path(hasher, leaf_index, leaf_count).ok_or(MerkleTreeError::InvalidLeafIndex)?;
+    if proof.len() != levels {
+        return Err(MerkleTreeError::InvalidProof.into());
+    }
+
+    let mut current = leaf_hash;
+    let mut index = path;
+    let mut width = match hasher.layout() {
+        TreeLayout::DuplicateOdd => u64::from(leaf_count),
+        TreeLayout::Complete => 1 << levels,
+    };
+
+    for sibling in proof {
+        current = if index % 2 == 0 {
+            let right = if u64::from(index) + 1 < width {
+                sibling
+            } else {
+                &current
+            };
+            hasher.hash_node(&current, right)
+        } else {
+            hasher.hash_node(sibling, &current)
+        };
+        index /= 2;
+        width = width.div_ceil(2);
+    }
+
+    Ok(current)
+}
+
+/// A proof for several leaves of the same tree at once, sharing the siblings
+/// their paths have in common.
+///
+/// Unlike single proofs, a multiproof has no entry for a node that is paired
+/// with itself: the verifier knows from the leaf count where that happens.
+#[derive(BorshSerialize, BorshDeserialize, Clone, Debug, PartialEq)]
+pub struct MultiProof {
+    /// Indices of the proven leaves, strictly ascending.
+    pub leaf_indices: Vec<u32>,
+
+    /// Hashes of the proven leaves, in the order of `leaf_indices`.
+    pub leaves: Vec<Hash>,
+
+    /// Siblings that cannot be computed from the proven leaves, level by level
+    /// from the leaves up and from left to right within a level.
+    pub siblings: Vec<Hash>,
+}
+
+/// Appends to `siblings` the nodes of `level` a multiproof needs next to the
+/// `known` nodes (ascending indices), and returns the indices of their parents.
+pub fn push_multiproof_siblings(
+    level: &[Hash],
+    known: &[usize],
+    siblings: &mut Vec<Hash>,
+) -> Vec<usize> {
+    let mut parents = Vec::with_capacity(known.len());
+    let mut k = 0;
+    while k < known.len() {
+        let index = known[k];
+        let pair_known = index % 2 == 0 && known.get(k + 1) == Some(&(index + 1));
+        if pair_known {
+            k += 1;
+        } else if let Some(sibling) = level.get(index ^ 1) {
+            siblings.push(*sibling);
+        }
+        parents.push(index / 2);
+        k += 1;
+    }
+    parents
+}
+
+/// Appends to `siblings` the leaves a multiproof needs next to the `known`
+/// leaves (ascending indices) among at least two `leaves`, and returns the
+/// indices of their nodes on the level above.
+pub fn push_multiproof_leaf_siblings<H: MerkleHasher>(
+    hasher: &H,
+    leaves: &[Hash],
+    known: &[usize],
+    siblings: &mut Vec<Hash>,
+) -> Vec<usize> {
+    let bottom_width = bottom_width(hasher, leaves.len());
+    let (bottom, upper) = known.split_at(known.partition_point(|&index| index < bottom_width));
+    let parents = push_multiproof_siblings(&leaves[..bottom_width], bottom, siblings);
+    upper
+        .iter()
+        .map(|index| index - bottom_width)
+        .chain(
+            parents
+                .into_iter()
+                .map(|parent| leaves.len() - bottom_width + parent),
+        )
+        .collect()
+}
+
+/// Recomputes the root of a tree with `leaf_count` leaves from a multiproof.
+///
+/// Fails if the indices are not strictly ascending or out of range, or if the
+/// proof has too few or too many siblings.
+pub fn recompute_root_from_multiproof<H: MerkleHasher>(
+    hasher: &H,
+    leaf_count: u32,
+    proof: &MultiProof,
+) -> Result<Hash, ProgramError> {
+    let indices = &proof.leaf_indices;
+    if indices.is_empty() || indices.len() != proof.leaves.len() {
+        return Err(MerkleTreeError::InvalidProof.into());
+    }
+    if indices.windows(2).any(|pair| pair[0] >= pair[1]) || indices[indices.len() - 1] >= leaf_count
+    {
+        return Err(MerkleTreeError::InvalidLeafIndex.into());
+    }
+
+    let mut nodes = indices
+        .iter()
+        .copied()
+        .zip(proof.leaves.iter().copied())
+        .collect::<Vec<_>>();
+    let mut siblings = proof.siblings.iter();
+
+    // Combine the known nodes level by level, taking each missing sibling from
+    // the proof in the order `push_multiproof_siblings` emitted them. In a
+    // complete tree, the leaves above the bottom level join the parents of the
+    // bottom pairs on the level above.
+    let mut width = leaf_count;
+    if leaf_count > 1 {
+        let bottom_width = bottom_width(hasher, leaf_count as usize) as u32;
+        let upper = nodes.split_off(nodes.partition_point(|&(index, _)| index < bottom_width));
+        let parents = hash_known_nodes(hasher, &nodes, bottom_width, &mut siblings)?;
+        nodes = upper
+            .into_iter()
+            .map(|(index, node)| (index - bottom_width, node))
+            .chain(
+                parents
+                    .into_iter()
+                    .map(|(parent, node)| (leaf_count - bottom_width + parent, node)),
+            )
+            .collect();
+        width = leaf_count - bottom_width + bottom_width.div_ceil(2);
+    }
+    while width > 1 {
+        nodes = hash_known_nodes(hasher, &nodes, width, &mut siblings)?;
+        width = width.div_ceil(2);
+    }
+
+    // Leftover siblings mean the proof was built for a different tree.
+    if siblings.next().is_some() {
+        return Err(MerkleTreeError::InvalidProof.into());
+    }
+
+    Ok(nodes[0].1)
+}
+
+/// Hashes the known `nodes` (ascending indices) of a level `width` nodes wide
+/// into their parents, taking the siblings that are not known from `siblings`.
+fn hash_known_nodes<H: MerkleHasher>(
+    hasher: &H,
+    nodes: &[(u32, Hash)],
+    width: u32,
+    siblings: &mut std::slice::Iter<Hash>,
+) -> Result<Vec<(u32, Hash)>, ProgramError> {
+    let mut next_sibling = || siblings.next().ok_or(MerkleTreeError::InvalidProof);
+    let mut parents = Vec::with_capacity(nodes.len());
+    let mut k = 0;
+    while k < nodes.len() {
+        let (index, node) = nodes[k];
+        let parent = if index % 2 == 0 {
+            let right = match nodes.get(k + 1) {
+                Some(&(right_index, right)) if right_index == index + 1 => {
+                    k += 1;
+                    right
+                }
+                _ if index + 1 < width => *next_sibling()?,
+                _ => node,
+            };
+            hasher.hash_node(&node, &right)
+        } else {
+            hasher.hash_node(next_sibling()?, &node)
+        };
+        parents.push((index / 2, parent));
+        k += 1;
+    }
+    Ok(parents)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        merkle_tree::MerkleTree,
+        state::{HashFunction, NodeHashing},
+    };
+
+    fn configs() -> [TreeConfig; 3] {
+        [
+            TreeConfig::default(),
+            TreeConfig {
+                hash_function: HashFunction::Keccak256,
+                node_hashing: NodeHashing::SortedPair,
+                domain_separated: false,
+            },
+            TreeConfig {
+                domain_separated: true,
+                ..TreeConfig::default()
+            },
+        ]
+    }
+
+    fn leaf_hashes(config: &TreeConfig, count: usize) -> Vec<Hash> {
+        (0..count)
+            .map(|i| config.hash_leaf(format!("leaf {i}").as_bytes()))
+            .collect()
+    }
+
+    #[test]
+    fn every_proof_verifies_against_the_root() {
+        for config in configs() {
+            for count in 1..=17 {
+                let leaves = leaf_hashes(&config, count);
+                let root = build_merkle_root(&config, &leaves).unwrap();
+                for (index, leaf) in leaves.iter().enumerate() {
+                    let proof = build_proof(&config, index, &leaves).unwrap();
+                    let (_, levels) = leaf_path(&config, index as u32, count as u32).unwrap();
+                    assert_eq!(proof.len(), levels);
+                    let recomputed =
+                        recompute_tree_root(&config, *leaf, index as u32, count as u32, &proof)
+                            .unwrap();
+                    assert_eq!(
+                        recomputed, root,
+                        "{config:?}, {count} leaves, index {index}"
+                    );
+                }
+            }
+        }
+    }
+
+    /// OpenZeppelin's `makeMerkleTree`: node `i` of the returned array is the
+    /// parent of nodes `2i + 1` and `2i + 2`, and the leaves fill the end of
+    /// the array in reverse order.
+    fn openzeppelin_tree(config: &TreeConfig, leaves: &[Hash]) -> Vec<Hash> {
+        let mut tree = vec![Hash::default(); 2 * leaves.len() - 1];
+        let len = tree.len();
+        for (i, leaf) in leaves.iter().enumerate() {
+            tree[len - 1 - i] = *leaf;
+        }
+        for i in (0..len - leaves.len()).rev() {
+            tree[i] = config.hash_node(&tree[2 * i + 1], &tree[2 * i + 2]);
+        }
+        tree
+    }
+
+    /// OpenZeppelin's `getProof` for the leaf at `leaf_index`.
+    fn openzeppelin_proof(tree: &[Hash], leaf_index: usize) -> Vec<Hash> {
+        let mut node = tree.len() - 1 - leaf_index;
+        let mut proof = Vec::new();
+        while node > 0 {
+            let sibling = if node % 2 == 1 { node + 1 } else { node - 1 };
+            proof.push(tree[sibling]);
+            node = (node - 1) / 2;
+        }
+        proof
+    }
+
+    #[test]
+    fn complete_trees_match_openzeppelin() {
+        let config = configs()[1];
+        assert_eq!(config.layout(), TreeLayout::Complete);
+        for count in 1..=17 {
+            let leaves = leaf_hashes(&config, count);
+            let tree = openzeppelin_tree(&config, &leaves);
+            assert_eq!(build_merkle_root(&config, &leaves).unwrap(), tree[0]);
+            for index in 0..count {
+                assert_eq!(
+                    build_proof(&config, index, &leaves).unwrap(),
+                    openzeppelin_proof(&tree, index),
+                    "{count} leaves, index {index}"
+                );
+            }
+        }
+    }
+
+    fn multiproof(config: &TreeConfig, leaves: &[Hash], leaf_indices: &[u32]) -> MultiProof {
+        MerkleTree::from_leaf_hashes(*config, leaves.to_vec())
+            .unwrap()
+            .multiproof(leaf_indices)
+            .unwrap()
+    }
+
+    #[test]
+    fn multiproofs_pair_an_odd_last_node_with_itself() {
+        let config = TreeConfig::default();
+        let leaves = leaf_hashes(&config, 5);
+        let root = build_merkle_root(&config, &leaves).unwrap();
+
+        // Leaf 4 is paired with itself twice; only the left half of the tree
+        // is needed next to it.
+        let proof = multiproof(&config, &leaves, &[4]);
+        assert_eq!(
+            proof.siblings,
+            [build_merkle_root(&config, &leaves[..4]).unwrap()]
+        );
+        assert_eq!(recompute_root_from_multiproof(&config, 5, &proof), Ok(root));
+
+        let proof = multiproof(&config, &leaves, &[3, 4]);
+        assert_eq!(proof.siblings.len(), 2);
+        assert_eq!(recompute_root_from_multiproof(&config, 5, &proof), Ok(root));
+    }
+
+    #[test]
+    fn multiproofs_reject_extra_or_missing_siblings() {
+        for config in configs() {
+            let leaves = leaf_hashes(&config, 6);
+            let proof = multiproof(&config, &leaves, &[1, 3]);
+
+            let mut extra = proof.clone();
+            extra.siblings.push(leaves[0]);
+            let mut missing = proof.clone();
+            missing.siblings.pop();
+            for proof in [extra, missing] {
+                assert_eq!(
+                    recompute_root_from_multiproof(&config, 6, &proof),
+                    Err(MerkleTreeError::InvalidProof.into())
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn multiproofs_reject_unsorted_or_duplicate_indices() {
+        let config = TreeConfig::default();
+        let leaves = leaf_hashes(&config, 6);
+        let proof = multiproof(&config, &leaves, &[1, 3]);
+
+        let unsorted = MultiProof {
+            leaf_indices: vec![3, 1],
+            leaves: vec![leaves[3], leaves[1]],
+            ..proof.clone()
+        };
+        let duplicate = MultiProof {
+            leaf_indices: vec![1, 1],
+            leaves: vec![leaves[1], leaves[1]],
+            ..proof
+        };
+        for proof in [unsorted, duplicate] {
+            assert_eq!(
+                recompute_root_from_multiproof(&config, 6, &proof),
+                Err(MerkleTreeError::InvalidLeafIndex.into())
+            );
+        }
+    }
+
+    #[test]
+    fn multiproofs_reject_indices_outside_the_tree() {
+        let config = TreeConfig::default();
+        let leaves = leaf_hashes(&config, 6);
+        let proof = multiproof(&config, &leaves, &[1, 5]);
+        assert_eq!(
+            recompute_root_from_multiproof(&config, 5, &proof),
+            Err(MerkleTreeError::InvalidLeafIndex.into())
+        );
+        assert!(MerkleTree::from_leaf_hashes(config, leaves)
+            .unwrap()
+            .multiproof(&[6])
+            .is_err());
+    }
+
+    #[test]
+    fn domain_separation_rejects_inner_nodes_posing_as_leaves() {
+        for domain_separated in [false, true] {
+            let config = TreeConfig {
+                domain_separated,
+                ..TreeConfig::default()
+            };
+            let leaves = leaf_hashes(&config, 4);
+            let root = build_merkle_root(&config, &leaves).unwrap();
+
+            // Present the first inner node's children as 64 bytes of leaf data,
+            // with a proof one level shorter than a real one.
+            let forged_leaf = [leaves[0].to_bytes(), leaves[1].to_bytes()].concat();
+            let right_node = config.hash_node(&leaves[2], &leaves[3]);
+            let recomputed =
+                recompute_merkle_root_from_proof(&config, &forged_leaf, 0, &[right_node]).unwrap();
+
+            assert_eq!(recomputed == root, !domain_separated);
+        }
+    }
+}
@@ -1,21 +1,23 @@
-use borsh::{BorshDeserialize, BorshSerialize};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use borsh::BorshDeserialize;
 use solana_client::rpc_client::RpcClient;
+use solana_native_merkle_tree::{
+    instruction,
+    merkle_tree::MerkleTree,
+    state::{MerkleTreeAccount, TreeConfig},
+    utils::MerkleProof,
+};
 use solana_sdk::{
     commitment_config::CommitmentConfig,
-    instruction::{AccountMeta, Instruction},
     pubkey::Pubkey,
+    rent::Rent,
     signature::{Keypair, Signer},
     system_instruction,
     transaction::Transaction,
 };
-use solana_transaction_status::UiTransactionEncoding;
+use solana_transaction_status::{UiTransactionEncoding, UiTransactionReturnData};
 use std::str::FromStr;
 
-#[derive(BorshSerialize, BorshDeserialize, Debug)]
-struct MerkleTreeInput {
-    pub leaves: Vec<Vec<u8>>,
-}
-
 #[tokio::main]
 async fn main() {
     // Solana program ID (replace with your deployed program ID)
@@ -44,21 +46,6 @@ async fn main() {
     // Create a new keypair for the Merkle tree account
     let tree_account = Keypair::new();
 
-    // Calculate the space required for the Merkle tree account
-    let account_space = 1024; // Ensure this matches your `MerkleTreeAccount::LEN`
-
-    // Create the Merkle tree account
-    let rent_lamports = client
-        .get_minimum_balance_for_rent_exemption(account_space)
-        .unwrap();
-    let create_account_instruction = system_instruction::create_account(
-        &payer.pubkey(),
-        &tree_account.pubkey(),
-        rent_lamports,
-        account_space as u64,
-        &program_id,
-    );
-
     // Generate sample transaction IDs as test data
     let tx_ids = vec![
         "f4eecb34d9274d1b99b3d3ccafe8a9e7",
@@ -73,24 +60,29 @@ async fn main() {
         .map(|tx| tx.as_bytes().to_vec())
         .collect();
 
-    // Construct the struct
-    let input = MerkleTreeInput { leaves };
+    // Size the account for exactly these leaves, without root history
+    let account_space = MerkleTreeAccount::space_for(leaves.len(), 0);
+    let rent_lamports = MerkleTreeAccount::minimum_balance(&Rent::default(), leaves.len(), 0);
 
-    // Serialize the Merkle tree input
-    let mut instruction_data: Vec<u8> = Vec::new();
-    input
-        .serialize(&mut instruction_data)
-        .expect("Failed to serialize");
+    // Create the Merkle tree account
+    let create_account_instruction = system_instruction::create_account(
+        &payer.pubkey(),
+        &tree_account.pubkey(),
+        rent_lamports,
+        account_space as u64,
+        &program_id,
+    );
 
     // Build the instruction to initialize the Merkle tree
-    let init_tree_instruction = Instruction::new_with_borsh(
-        program_id,
-        &instruction_data, // Serialized leaves as input
-        vec![
-            AccountMeta::new(tree_account.pubkey(), false),
-            AccountMeta::new(payer.pubkey(), true),
-        ],
-    );
+    let init_tree_instruction = instruction::build_tree(
+        &program_id,
+        &tree_account.pubkey(),
+        &payer.pubkey(),
+        TreeConfig::default(),
+        &leaves,
+        0,
+    )
+    .expect("Failed to serialize");
 
     // Build and send the transaction
     let mut transaction = Transaction::new_with_payer(
@@ -110,16 +102,9 @@ async fn main() {
 
     // Generate a proof for the first leaf (index 0)
     let leaf_index: u32 = 0;
-    let mut proof_instruction_data: Vec<u8> = Vec::new();
-    leaf_index
-        .serialize(&mut proof_instruction_data)
-        .expect("Failed to serialize proof request");
-
-    let build_proof_instruction = Instruction::new_with_borsh(
-        program_id,
-        &proof_instruction_data, // Leaf index as input
-        vec![AccountMeta::new(tree_account.pubkey(), false)],
-    );
+    let build_proof_instruction =
+        instruction::build_proof(&program_id, &tree_account.pubkey(), leaf_index, false)
+            .expect("Failed to serialize proof request");
 
     let mut proof_transaction =
         Transaction::new_with_payer(&[build_proof_instruction], Some(&payer.pubkey()));
@@ -137,5 +122,31 @@ async fn main() {
         }
     };
 
-    // TODO: Fetch the proof from the logs
+    // Fetch the proof from the transaction's return data
+    let proof_transaction = client
+        .get_transaction(&proof_signature, UiTransactionEncoding::Json)
+        .expect("Failed to fetch proof transaction");
+    let return_data: Option<UiTransactionReturnData> = proof_transaction
+        .transaction
+        .meta
+        .and_then(|meta| meta.return_data.into());
+    let Some(return_data) = return_data else {
+        eprintln!("Proof transaction has no return data");
+        return;
+    };
+
+    let proof_bytes = STANDARD
+        .decode(&return_data.data.0)
+        .expect("Failed to decode return data");
+    let proof = MerkleProof::try_from_slice(&proof_bytes).expect("Failed to deserialize proof");
+    println!("Proof for leaf {}:", proof.leaf_index);
+    for sibling in &proof.siblings {
+        println!("  {}", sibling);
+    }
+
+    // The same tree built locally yields the same root and proof
+    let local_tree =
+        MerkleTree::new(TreeConfig::default(), &leaves).expect("Failed to build local tree");
+    println!("Local root: {}", local_tree.root());
+    println!("Proof valid: {}", local_tree.verify(&proof));
 }